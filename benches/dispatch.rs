@@ -0,0 +1,123 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hero_rs::{
+    clock::{Clock, ManualClock},
+    config::{ActionKey, ComboConfig, Conditions, Config, StepConfig, TriggerType},
+    engine::{Combo, Engine},
+    events::EventBus,
+    input::event_at,
+    log::{self, Level},
+    pattern::Pattern,
+};
+use rdev::{Button, EventType, Key};
+use std::sync::Arc;
+
+const KEYS: [Key; 12] = [
+    Key::KeyQ,
+    Key::KeyW,
+    Key::KeyE,
+    Key::KeyR,
+    Key::KeyD,
+    Key::KeyF,
+    Key::KeyA,
+    Key::KeyS,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Space,
+];
+
+// 生成 500 个不同类型的触发条件
+fn triggers() -> Vec<TriggerType> {
+    (0..500)
+        .map(|i| {
+            let key = |n: usize| KEYS[(i * 7 + n * 3) % KEYS.len()];
+            match i % 5 {
+                0 => TriggerType::SingleKey { key: key(0) },
+                1 => TriggerType::KeyModifier {
+                    modifier: Key::ShiftLeft,
+                    key: key(0),
+                },
+                2 => TriggerType::KeySequence {
+                    keys: vec![key(0), key(1), key(2)],
+                    timeout_ms: 300,
+                    max_interleaved: 0,
+                    ignore_keys: Vec::new(),
+                },
+                3 => TriggerType::KeySequence {
+                    keys: vec![key(0), key(1)],
+                    timeout_ms: 150,
+                    max_interleaved: 1,
+                    ignore_keys: Vec::new(),
+                },
+                _ => TriggerType::Pattern {
+                    pattern: Pattern::parse(&format!(
+                        "{:?} [{:?} {:?}]? {:?} <150ms",
+                        key(0),
+                        key(1),
+                        key(2),
+                        key(3)
+                    ))
+                    .unwrap(),
+                },
+            }
+        })
+        .collect()
+}
+
+// 每个触发条件对应一个连招，部分连招带有按住按键、英雄和优先级条件
+fn combos() -> Vec<Combo> {
+    triggers()
+        .into_iter()
+        .enumerate()
+        .map(|(i, trigger)| {
+            let mut conditions = Conditions::default();
+            match i % 4 {
+                0 => conditions.held.push(ActionKey::Mouse(Button::Right)),
+                1 => conditions.not_held.push(ActionKey::Keyboard(Key::Alt)),
+                2 => conditions.champions.push("yasuo".to_string()),
+                _ => {}
+            }
+            let config = ComboConfig {
+                name: format!("连招{}", i),
+                sequence: vec![StepConfig {
+                    key: ActionKey::Keyboard(KEYS[i % KEYS.len()]),
+                    delay_before_ms: 0,
+                    delay_after_ms: 0,
+                }],
+                trigger,
+                block_original_input: i % 3 == 0,
+                active: true,
+                conditions,
+                priority: (i % 7) as i32,
+            };
+            Combo::from_config(&config, 0)
+        })
+        .collect()
+}
+
+// 完整的输入处理路径：按键历史、触发索引、连招条件、判定和事件发布
+fn bench_handle_event(c: &mut Criterion) {
+    let clock = Arc::new(ManualClock::new());
+    let mut engine = Engine::new(Config::builtin(), clock.clone(), Arc::new(EventBus::new()));
+    engine.set_combos(combos());
+    // 只判定不执行，被屏蔽的按键不会影响之后的事件；
+    // 演习模式每次触发都会输出日志，关闭日志以免测量的是终端输出
+    engine.dry_run = true;
+    log::set_level(Level::Error);
+    let mut n = 0usize;
+
+    c.bench_function("handle_event_500_combos", |b| {
+        b.iter(|| {
+            n += 1;
+            let key = KEYS[(n * 5) % KEYS.len()];
+            clock.advance_ms(40);
+            let press = event_at(EventType::KeyPress(key), clock.system_now());
+            black_box(engine.handle_event(black_box(&press), None));
+            let release = event_at(EventType::KeyRelease(key), clock.system_now());
+            black_box(engine.handle_event(black_box(&release), None))
+        })
+    });
+}
+
+criterion_group!(benches, bench_handle_event);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// 时钟抽象，计时和等待都通过它进行，测试时可以替换为手动推进的时钟
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    // 当前的系统时间，用于换算操作系统提供的事件时间
+    fn system_now(&self) -> SystemTime;
+
+    fn sleep(&self, duration: Duration);
+}
+
+// 系统时钟
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// 测试用时钟：时间只在调用 advance 或 sleep 时前进，sleep 不会真正等待
+#[derive(Clone)]
+pub struct ManualClock {
+    base: Instant,
+    system_base: SystemTime,
+    offset: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            base: Instant::now(),
+            system_base: SystemTime::now(),
+            offset: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.offset.lock().unwrap() += duration;
+    }
+
+    pub fn advance_ms(&self, ms: u64) {
+        self.advance(Duration::from_millis(ms));
+    }
+
+    // 自创建以来经过的时间
+    pub fn elapsed(&self) -> Duration {
+        *self.offset.lock().unwrap()
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system_base + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+// 把系统时间（例如操作系统提供的事件时间）换算为单调时钟上的时间点，同时返回事件到达的延迟
+// 事件时间晚于当前时间（系统时间被调整）时视为没有延迟
+pub fn to_monotonic(clock: &dyn Clock, time: SystemTime) -> (Instant, Duration) {
+    let now = clock.now();
+    let lag = clock
+        .system_now()
+        .duration_since(time)
+        .unwrap_or(Duration::ZERO);
+    (now.checked_sub(lag).unwrap_or(now), lag)
+}
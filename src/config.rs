@@ -1,16 +1,859 @@
-use rdev::Key;
+use crate::input::DeviceInfo;
+use crate::pattern::{key_name, parse_key, Pattern};
+use rdev::{Button, Key};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table};
+
+// 配置文件名
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+// 定义按键类型，可以是键盘按键或鼠标按钮
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ActionKey {
+    Keyboard(Key),
+    Mouse(Button),
+}
+
+impl ActionKey {
+    // 从文本解析：鼠标按钮写作 mouse:left、mouse:right、mouse:middle 或 mouse:<编号>，
+    // 其他按 parse_key 解析为键盘按键
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let Some(button) = text.strip_prefix("mouse:") else {
+            return parse_key(text).map(ActionKey::Keyboard);
+        };
+        let button = match button.to_lowercase().as_str() {
+            "left" => Button::Left,
+            "right" => Button::Right,
+            "middle" => Button::Middle,
+            other => Button::Unknown(
+                other
+                    .parse()
+                    .map_err(|_| format!("未知鼠标按钮: {}", button))?,
+            ),
+        };
+        Ok(ActionKey::Mouse(button))
+    }
+
+    // 与 parse 互逆的文本表示
+    pub fn name(&self) -> String {
+        match self {
+            ActionKey::Keyboard(key) => key_name(*key),
+            ActionKey::Mouse(Button::Left) => "mouse:left".to_string(),
+            ActionKey::Mouse(Button::Right) => "mouse:right".to_string(),
+            ActionKey::Mouse(Button::Middle) => "mouse:middle".to_string(),
+            ActionKey::Mouse(Button::Unknown(code)) => format!("mouse:{}", code),
+        }
+    }
+}
 
 // 触发类型枚举
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
 pub enum TriggerType {
-    SingleKey(Key),
+    SingleKey {
+        key: Key,
+    },
     KeySequence {
         keys: Vec<Key>,
         timeout_ms: u64,
+        // 序列按键之间最多允许夹杂的无关按键数量
+        #[serde(default, skip_serializing_if = "is_default")]
+        max_interleaved: usize,
+        // 匹配时忽略的按键（例如移动或镜头按键），不计入 max_interleaved
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        ignore_keys: Vec<Key>,
     },
     KeyModifier {
         modifier: Key,
         key: Key,
     },
+    // 模式语法触发，例如 "E [W]? (R|D) <150ms"
+    Pattern {
+        #[serde(deserialize_with = "deserialize_pattern")]
+        pattern: Pattern,
+    },
     Manual,
-}
\ No newline at end of file
+}
+
+impl TriggerType {
+    // 触发条件的具体程度（至少需要的按键数），优先级相同时更具体的触发胜出
+    pub fn specificity(&self) -> usize {
+        match self {
+            TriggerType::SingleKey { .. } => 1,
+            TriggerType::KeyModifier { .. } => 2,
+            TriggerType::KeySequence { keys, .. } => keys.len(),
+            TriggerType::Pattern { pattern } => pattern.min_keys(),
+            TriggerType::Manual => 0,
+        }
+    }
+
+    // 从文本解析触发条件，用于在命令行中创建连招：
+    //   key E                   单个按键
+    //   seq E,R 150ms           按键序列及超时时间
+    //   mod ShiftLeft+Y         修饰键组合
+    //   pattern E [W]? (R|D)    模式语法
+    //   manual                  只能手动执行
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (kind, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let rest = rest.trim();
+        match kind.to_lowercase().as_str() {
+            "key" => Ok(TriggerType::SingleKey {
+                key: parse_key(rest)?,
+            }),
+            "seq" => {
+                let mut words: Vec<&str> = rest
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|word| !word.is_empty())
+                    .collect();
+                let timeout = words
+                    .pop()
+                    .and_then(|word| word.strip_suffix("ms"))
+                    .ok_or("按键序列需要以超时时间结尾，例如 seq E,R 150ms")?;
+                let timeout_ms = timeout
+                    .parse()
+                    .map_err(|_| format!("无效的超时时间: {}ms", timeout))?;
+                Ok(TriggerType::KeySequence {
+                    keys: words.into_iter().map(parse_key).collect::<Result<_, _>>()?,
+                    timeout_ms,
+                    max_interleaved: 0,
+                    ignore_keys: Vec::new(),
+                })
+            }
+            "mod" => {
+                let (modifier, key) = rest
+                    .split_once('+')
+                    .ok_or("修饰键组合的格式为 mod ShiftLeft+Y")?;
+                Ok(TriggerType::KeyModifier {
+                    modifier: parse_key(modifier.trim())?,
+                    key: parse_key(key.trim())?,
+                })
+            }
+            "pattern" => Ok(TriggerType::Pattern {
+                pattern: Pattern::parse(rest)?,
+            }),
+            "manual" if rest.is_empty() => Ok(TriggerType::Manual),
+            _ => Err(format!(
+                "无法识别的触发条件: {}（可用 key、seq、mod、pattern、manual）",
+                text
+            )),
+        }
+    }
+
+    // parse 接受的文本表示（按键序列的 max_interleaved 和 ignore_keys 不包括在内）
+    pub fn to_text(&self) -> String {
+        match self {
+            TriggerType::SingleKey { key } => format!("key {}", key_name(*key)),
+            TriggerType::KeySequence {
+                keys, timeout_ms, ..
+            } => {
+                let keys: Vec<String> = keys.iter().map(|key| key_name(*key)).collect();
+                format!("seq {} {}ms", keys.join(","), timeout_ms)
+            }
+            TriggerType::KeyModifier { modifier, key } => {
+                format!("mod {}+{}", key_name(*modifier), key_name(*key))
+            }
+            TriggerType::Pattern { pattern } => format!("pattern {}", pattern.source()),
+            TriggerType::Manual => "manual".to_string(),
+        }
+    }
+}
+
+fn deserialize_pattern<'de, D>(deserializer: D) -> Result<Pattern, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let source = String::deserialize(deserializer)?;
+    Pattern::parse(&source).map_err(serde::de::Error::custom)
+}
+
+// 输入设备筛选条件，给出的字段全部满足时匹配
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeviceFilter {
+    // 设备名称包含该文本（不区分大小写）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<u16>,
+    // 设备文件路径，可以是 /dev/input/by-id 下的符号链接
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    // 符号链接指向的实际路径，加载配置时解析一次，避免每次按键都访问文件系统
+    #[serde(skip)]
+    resolved_path: Option<PathBuf>,
+}
+
+impl DeviceFilter {
+    // 解析 path 指向的实际设备文件；加载配置时设备尚未连接的，重新加载配置后生效
+    pub fn resolve(&mut self) {
+        self.resolved_path = self
+            .path
+            .as_ref()
+            .and_then(|path| fs::canonicalize(path).ok());
+    }
+
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        let name_matches = self
+            .name
+            .as_ref()
+            .is_none_or(|name| device.name.to_lowercase().contains(&name.to_lowercase()));
+        let path_matches = self.path.as_ref().is_none_or(|path| {
+            *path == device.path || self.resolved_path.as_ref() == Some(&device.path)
+        });
+        name_matches
+            && self.vendor.is_none_or(|vendor| vendor == device.vendor)
+            && self.product.is_none_or(|product| product == device.product)
+            && path_matches
+    }
+}
+
+// 连招的附加触发条件，与任意触发类型组合使用，全部满足时才会触发
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Conditions {
+    // 这些按键必须处于按下状态（例如只在按住鼠标右键时触发）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub held: Vec<ActionKey>,
+    // 这些按键必须处于松开状态
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub not_held: Vec<ActionKey>,
+    // 只在这些英雄下触发（为空表示不限制）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub champions: Vec<String>,
+    // 只在这些层中至少一个激活时触发（为空表示不限制）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<String>,
+    // 暂停时仍然允许触发
+    #[serde(skip_serializing_if = "is_default")]
+    pub allow_when_paused: bool,
+    // 聊天框打开时仍然允许触发
+    #[serde(skip_serializing_if = "is_default")]
+    pub allow_in_chat: bool,
+    // 只响应来自这些设备的按键（为空时使用 general.devices）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceFilter>,
+}
+
+impl Conditions {
+    // 没有任何附加条件
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+            && self.not_held.is_empty()
+            && self.champions.is_empty()
+            && self.layers.is_empty()
+            && !self.allow_when_paused
+            && !self.allow_in_chat
+            && self.devices.is_empty()
+    }
+}
+
+// 通用设置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GeneralConfig {
+    pub history_size: usize,
+    pub history_timeout_ms: u64,
+    // 模拟按键时按下与释放之间的间隔
+    pub default_delay_ms: u64,
+    // 多个连招同时匹配时全部触发，而不是只触发最优的一个
+    pub fire_all_matches: bool,
+    // 事件从发生到被处理的延迟超过该值时输出警告
+    pub lag_warning_ms: u64,
+    // 所有连招默认只响应来自这些设备的按键（为空表示不限制）
+    pub devices: Vec<DeviceFilter>,
+}
+
+impl Default for GeneralConfig {
+    fn default() -> Self {
+        GeneralConfig {
+            history_size: 20,
+            history_timeout_ms: 2000,
+            default_delay_ms: 50,
+            fire_all_matches: false,
+            lag_warning_ms: 30,
+            devices: Vec::new(),
+        }
+    }
+}
+
+// 连招中的一步操作
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StepConfig {
+    pub key: ActionKey,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub delay_before_ms: u64,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub delay_after_ms: u64,
+}
+
+impl StepConfig {
+    // 从文本解析一步操作：按键 [按下前延迟] [按下后延迟]，例如 "Q 0 50" 或 "mouse:left"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let (key, delays) = match words.as_slice() {
+            [key, delays @ ..] if delays.len() <= 2 => (key, delays),
+            _ => {
+                return Err(format!(
+                    "步骤的格式为 按键 [之前等待毫秒] [之后等待毫秒]: {}",
+                    text
+                ))
+            }
+        };
+        let delay = |i: usize| -> Result<u64, String> {
+            delays.get(i).map_or(Ok(0), |ms| {
+                ms.trim_end_matches("ms")
+                    .parse()
+                    .map_err(|_| format!("无效的延迟: {}", ms))
+            })
+        };
+        Ok(StepConfig {
+            key: ActionKey::parse(key)?,
+            delay_before_ms: delay(0)?,
+            delay_after_ms: delay(1)?,
+        })
+    }
+
+    // parse 接受的文本表示
+    pub fn to_text(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.key.name(),
+            self.delay_before_ms,
+            self.delay_after_ms
+        )
+    }
+}
+
+// 单个连招配置
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ComboConfig {
+    pub name: String,
+    pub sequence: Vec<StepConfig>,
+    pub trigger: TriggerType,
+    #[serde(default)]
+    pub block_original_input: bool,
+    #[serde(default = "default_true")]
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Conditions::is_empty")]
+    pub conditions: Conditions,
+    // 多个连招同时匹配时优先级高的胜出
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub priority: i32,
+}
+
+impl ComboConfig {
+    // 检查连招是否有效，加载配置文件和在命令行中编辑连招使用同样的规则
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("连招名称不能为空".to_string());
+        }
+        if self.sequence.is_empty() {
+            return Err(format!("连招 {} 没有任何步骤", self.name));
+        }
+        if let TriggerType::KeySequence {
+            keys, timeout_ms, ..
+        } = &self.trigger
+        {
+            if keys.is_empty() {
+                return Err(format!("连招 {} 的按键序列为空", self.name));
+            }
+            if *timeout_ms == 0 {
+                return Err(format!("连招 {} 的超时时间必须大于 0", self.name));
+            }
+        }
+        // 不需要任何按键就能匹配的模式会在每次按键时触发
+        if let TriggerType::Pattern { pattern } = &self.trigger {
+            if pattern.min_keys() == 0 {
+                return Err(format!(
+                    "连招 {} 的模式 \"{}\" 至少要匹配一个按键",
+                    self.name,
+                    pattern.source()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// 英雄特定配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChampionConfig {
+    pub name: String,
+    #[serde(default)]
+    pub combos: Vec<ComboConfig>,
+}
+
+// 钩子响应的事件，名称与事件流中的事件类型相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    // 切换英雄，包括启动时选择英雄和切换到全局连招
+    ChampionSwitched,
+    ComboTriggered,
+    PauseToggled,
+}
+
+// 事件发生时执行的本机命令，例如播放声音或者修改键盘灯光
+#[derive(Debug, Clone, Deserialize)]
+pub struct HookConfig {
+    pub event: HookEvent,
+    // 交给 sh -c（Windows 下为 cmd /C）执行，事件的内容通过 HERO_RS_ 开头的环境变量传入
+    pub command: String,
+    // 只响应这些连招（只用于 combo_triggered，为空表示不限制）
+    #[serde(default)]
+    pub combos: Vec<String>,
+    // 只响应切换到这些英雄（只用于 champion_switched，为空表示不限制）
+    #[serde(default)]
+    pub champions: Vec<String>,
+    // 超过该时间没有结束的命令会被终止
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_ms: u64,
+}
+
+fn default_hook_timeout() -> u64 {
+    5000
+}
+
+impl HookConfig {
+    pub fn validate(&self, config: &Config) -> Result<(), String> {
+        if self.command.trim().is_empty() {
+            return Err("钩子的命令不能为空".to_string());
+        }
+        if self.timeout_ms == 0 {
+            return Err(format!("钩子 {} 的 timeout_ms 必须大于 0", self.command));
+        }
+        if !self.combos.is_empty() && self.event != HookEvent::ComboTriggered {
+            return Err(format!(
+                "钩子 {}: 只有 combo_triggered 钩子可以限制连招",
+                self.command
+            ));
+        }
+        if !self.champions.is_empty() && self.event != HookEvent::ChampionSwitched {
+            return Err(format!(
+                "钩子 {}: 只有 champion_switched 钩子可以限制英雄",
+                self.command
+            ));
+        }
+        if let Some(name) = self
+            .champions
+            .iter()
+            .find(|name| config.find_champion(name).is_none())
+        {
+            return Err(format!("钩子 {}: 配置中没有英雄 {}", self.command, name));
+        }
+        Ok(())
+    }
+}
+
+// 完整配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // 启动时使用的英雄（命令行的 --profile 和上次使用的英雄优先）
+    pub current_champion: Option<String>,
+    // 启动时依次执行的命令，与命令行中输入的命令相同
+    pub on_start: Vec<String>,
+    pub general: GeneralConfig,
+    pub global_combos: Vec<ComboConfig>,
+    pub champion_specific: HashMap<String, ChampionConfig>,
+    pub hooks: Vec<HookConfig>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+// 检查一组连招，同一组中的连招名称不能重复
+fn validate_combos(scope: &str, combos: &[ComboConfig]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for combo in combos {
+        combo.validate().map_err(|e| format!("{}: {}", scope, e))?;
+        if !names.insert(combo.name.as_str()) {
+            return Err(format!("{}: 连招名称 {} 重复", scope, combo.name));
+        }
+    }
+    Ok(())
+}
+
+impl Config {
+    // 按顺序查找配置文件：系统配置目录，然后是当前目录
+    pub fn find() -> Option<PathBuf> {
+        let mut candidates = Vec::new();
+        if let Some(dir) = dirs::config_dir() {
+            candidates.push(dir.join("hero-rs").join(CONFIG_FILE_NAME));
+        }
+        candidates.push(PathBuf::from(CONFIG_FILE_NAME));
+
+        candidates.into_iter().find(|path| path.is_file())
+    }
+
+    // 从文件加载配置
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("无法读取配置文件 {}: {}", path.display(), e))?;
+        let mut config = Self::parse(&text)
+            .map_err(|e| format!("配置文件 {} 格式错误: {}", path.display(), e))?;
+        config
+            .validate()
+            .map_err(|e| format!("配置文件 {} 无效: {}", path.display(), e))?;
+        config.resolve_devices();
+        Ok(config)
+    }
+
+    // 解析全局和所有连招的设备限制中的路径
+    pub fn resolve_devices(&mut self) {
+        let combos = self
+            .champion_specific
+            .values_mut()
+            .flat_map(|champion| champion.combos.iter_mut())
+            .chain(self.global_combos.iter_mut());
+        let filters = self
+            .general
+            .devices
+            .iter_mut()
+            .chain(combos.flat_map(|combo| combo.conditions.devices.iter_mut()));
+        for filter in filters {
+            filter.resolve();
+        }
+    }
+
+    // 检查所有连招和钩子是否有效
+    pub fn validate(&self) -> Result<(), String> {
+        for hook in &self.hooks {
+            hook.validate(self)?;
+        }
+        validate_combos("全局连招", &self.global_combos)?;
+        let mut champions: Vec<_> = self.champion_specific.iter().collect();
+        champions.sort_by_key(|(id, _)| id.as_str());
+        for (id, champion) in champions {
+            validate_combos(&format!("英雄 {}", id), &champion.combos)?;
+        }
+        Ok(())
+    }
+
+    // 从文本解析配置
+    pub fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    // 内置默认配置，在找不到配置文件时使用
+    pub fn builtin() -> Self {
+        let mut champion_specific = HashMap::new();
+        champion_specific.insert(
+            "Yasuo".to_string(),
+            ChampionConfig {
+                name: "亚索".to_string(),
+                combos: vec![
+                    // 按下E后150ms内按下R，屏蔽R执行QR
+                    ComboConfig {
+                        name: "亚索E+R触发QR".to_string(),
+                        sequence: vec![
+                            step(ActionKey::Keyboard(Key::KeyQ), 0, 50),
+                            step(ActionKey::Keyboard(Key::KeyR), 50, 0),
+                        ],
+                        trigger: TriggerType::KeySequence {
+                            keys: vec![Key::KeyE, Key::KeyR],
+                            timeout_ms: 150,
+                            max_interleaved: 0,
+                            ignore_keys: Vec::new(),
+                        },
+                        block_original_input: true,
+                        active: true,
+                        conditions: Conditions::default(),
+                        priority: 0,
+                    },
+                    // 按下E后150ms内按下D，屏蔽D执行QD
+                    ComboConfig {
+                        name: "亚索E+D触发QD".to_string(),
+                        sequence: vec![
+                            step(ActionKey::Keyboard(Key::KeyQ), 0, 50),
+                            step(ActionKey::Keyboard(Key::KeyD), 50, 0),
+                        ],
+                        trigger: TriggerType::KeySequence {
+                            keys: vec![Key::KeyE, Key::KeyD],
+                            timeout_ms: 150,
+                            max_interleaved: 0,
+                            ignore_keys: Vec::new(),
+                        },
+                        block_original_input: true,
+                        active: true,
+                        conditions: Conditions::default(),
+                        priority: 0,
+                    },
+                ],
+            },
+        );
+
+        Config {
+            current_champion: None,
+            on_start: Vec::new(),
+            general: GeneralConfig::default(),
+            // 按下 Tab 键执行 A -> 25ms -> 鼠标左键
+            global_combos: vec![ComboConfig {
+                name: "Tab触发A+左键".to_string(),
+                sequence: vec![
+                    step(ActionKey::Keyboard(Key::KeyA), 0, 25),
+                    step(ActionKey::Mouse(Button::Left), 0, 0),
+                ],
+                trigger: TriggerType::SingleKey { key: Key::Tab },
+                block_original_input: false,
+                active: true,
+                conditions: Conditions::default(),
+                priority: 0,
+            }],
+            champion_specific,
+            hooks: Vec::new(),
+        }
+    }
+
+    // 按配置键名或显示名称查找英雄（不区分大小写）
+    pub fn find_champion(&self, name: &str) -> Option<(&String, &ChampionConfig)> {
+        let name = name.to_lowercase();
+        self.champion_specific.iter().find(|(id, champion)| {
+            id.to_lowercase() == name || champion.name.to_lowercase() == name
+        })
+    }
+}
+
+fn step(key: ActionKey, delay_before_ms: u64, delay_after_ms: u64) -> StepConfig {
+    StepConfig {
+        key,
+        delay_before_ms,
+        delay_after_ms,
+    }
+}
+
+// 把连招写入配置文件，文件中的其他内容和注释保持不变
+// champion 为 None 时写入全局连招；名为 old_name 的连招存在时原位替换，否则追加到末尾
+pub fn save_combo(
+    path: &Path,
+    champion: Option<&str>,
+    old_name: Option<&str>,
+    combo: &ComboConfig,
+) -> Result<(), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("无法读取配置文件 {}: {}", path.display(), e))?;
+    let mut doc: DocumentMut = text
+        .parse()
+        .map_err(|e| format!("配置文件 {} 格式错误: {}", path.display(), e))?;
+
+    let item = match champion {
+        None => &mut doc["global_combos"],
+        Some(id) => &mut doc["champion_specific"][id]["combos"],
+    };
+    if item.is_none() {
+        *item = Item::ArrayOfTables(ArrayOfTables::new());
+    }
+    let combos = item
+        .as_array_of_tables_mut()
+        .ok_or("配置文件中的连招列表不是 [[...]] 表数组，无法自动修改")?;
+
+    let mut table = combo_table(combo)?;
+    let existing = old_name.and_then(|old_name| {
+        combos
+            .iter_mut()
+            .find(|table| table.get("name").and_then(Item::as_str) == Some(old_name))
+    });
+    match existing {
+        Some(existing) => {
+            table.decor_mut().clone_from(existing.decor());
+            if let Some(position) = existing.position() {
+                table.set_position(position);
+            }
+            *existing = table;
+        }
+        None => {
+            table.decor_mut().set_prefix("\n");
+            combos.push(table);
+        }
+    }
+
+    // 写入前确认修改后的文件仍然能通过加载时的检查
+    let text = doc.to_string();
+    Config::parse(&text)
+        .map_err(|e| e.to_string())?
+        .validate()?;
+    fs::write(path, text).map_err(|e| format!("无法写入配置文件 {}: {}", path.display(), e))
+}
+
+// 连招的 TOML 表，嵌套的表写成内联形式，与示例配置的格式一致
+fn combo_table(combo: &ComboConfig) -> Result<Table, String> {
+    let mut doc = toml_edit::ser::to_document(combo).map_err(|e| e.to_string())?;
+    let mut table = Table::new();
+    for key in [
+        "name",
+        "sequence",
+        "trigger",
+        "block_original_input",
+        "active",
+        "priority",
+        "conditions",
+    ] {
+        let Some(item) = doc.remove(key) else {
+            continue;
+        };
+        let mut value = item
+            .into_value()
+            .map_err(|_| format!("无法转换连招字段 {}", key))?;
+        value.decor_mut().clear();
+        table.insert(key, Item::Value(value));
+    }
+    // 每个步骤占一行
+    if let Some(sequence) = table.get_mut("sequence").and_then(Item::as_array_mut) {
+        for step in sequence.iter_mut() {
+            step.decor_mut().set_prefix("\n    ");
+        }
+        sequence.set_trailing("\n");
+        sequence.set_trailing_comma(false);
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_parse_text() {
+        let trigger = TriggerType::parse("seq E, R 150ms").unwrap();
+        assert!(matches!(
+            &trigger,
+            TriggerType::KeySequence { keys, timeout_ms: 150, .. } if *keys == [Key::KeyE, Key::KeyR]
+        ));
+        for text in [
+            "key Tab",
+            "seq E,R 150ms",
+            "mod ShiftLeft+Y",
+            "pattern E [W]? (R|D) <150ms",
+            "manual",
+        ] {
+            assert_eq!(TriggerType::parse(text).unwrap().to_text(), text);
+        }
+        assert!(TriggerType::parse("seq E,R").is_err());
+        assert!(TriggerType::parse("mod ShiftLeft").is_err());
+        assert!(TriggerType::parse("hold E").is_err());
+
+        let step = StepConfig::parse("mouse:left 20 50ms").unwrap();
+        assert_eq!(step.key, ActionKey::Mouse(Button::Left));
+        assert_eq!((step.delay_before_ms, step.delay_after_ms), (20, 50));
+        assert_eq!(StepConfig::parse("Q").unwrap().to_text(), "Q 0 0");
+        assert!(StepConfig::parse("Q 1 2 3").is_err());
+        assert!(StepConfig::parse("mouse:side").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = Config::builtin();
+        assert!(config.validate().is_ok());
+        let mut combo = config.global_combos[0].clone();
+        config.global_combos.push(combo.clone());
+        assert!(config.validate().unwrap_err().contains("重复"));
+
+        combo.sequence.clear();
+        assert!(combo.validate().is_err());
+        combo.sequence = config.global_combos[0].sequence.clone();
+        combo.trigger = TriggerType::parse("seq E,R 0ms").unwrap();
+        assert!(combo.validate().is_err());
+        // 可以匹配空输入的模式
+        for pattern in ["pattern E?", "pattern [W]*"] {
+            combo.trigger = TriggerType::parse(pattern).unwrap();
+            assert!(combo.validate().unwrap_err().contains("至少要匹配一个按键"));
+        }
+        combo.trigger = TriggerType::parse("pattern E? R").unwrap();
+        assert!(combo.validate().is_ok());
+
+        let config = Config::parse(
+            r#"
+            [[hooks]]
+            event = "champion_switched"
+            command = "paplay switch.ogg"
+            champions = ["亚索"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.hooks[0].timeout_ms, 5000);
+        assert!(config.validate().unwrap_err().contains("没有英雄 亚索"));
+        let mut hook = config.hooks[0].clone();
+        hook.event = HookEvent::PauseToggled;
+        hook.champions.clear();
+        assert!(hook.validate(&config).is_ok());
+        hook.combos.push("QWE连招".to_string());
+        assert!(hook
+            .validate(&config)
+            .unwrap_err()
+            .contains("combo_triggered"));
+    }
+
+    #[test]
+    fn test_save_combo() {
+        let path = env::temp_dir().join(format!("hero-rs-save-{}.toml", process::id()));
+        let original = include_str!("../config.toml");
+        fs::write(&path, original).unwrap();
+
+        let mut combo = Config::builtin().global_combos[0].clone();
+        combo.trigger = TriggerType::parse("pattern E R <150ms").unwrap();
+        save_combo(&path, None, None, &combo).unwrap();
+        combo.name = "亚索EQ闪".to_string();
+        combo.sequence.push(StepConfig::parse("F 10 0").unwrap());
+        save_combo(&path, Some("Yasuo"), Some("亚索EQ连招"), &combo).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        // 注释保持不变
+        assert!(text.contains("# 全局连招配置"));
+        let config = Config::parse(&text).unwrap();
+        assert_eq!(config.global_combos.len(), 3);
+        assert_eq!(
+            config.global_combos[2].trigger.to_text(),
+            "pattern E R <150ms"
+        );
+        let yasuo = &config.champion_specific["Yasuo"].combos;
+        assert_eq!(yasuo.len(), 1);
+        assert_eq!(yasuo[0].name, "亚索EQ闪");
+        assert_eq!(yasuo[0].sequence.len(), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_device_path_resolved_on_load() {
+        let dir = env::temp_dir().join(format!("hero-rs-devices-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("event7");
+        let link = dir.join("usb-keyboard-event-kbd");
+        fs::write(&target, "").unwrap();
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            format!("[general]\ndevices = [{{ path = {:?} }}]\n", link),
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        let device = DeviceInfo {
+            path: fs::canonicalize(&target).unwrap(),
+            name: "键盘".to_string(),
+            vendor: 1,
+            product: 2,
+        };
+        // 加载后不再访问文件系统，删除符号链接不影响匹配
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(config.general.devices[0].matches(&device));
+        let mut unresolved = config.general.devices[0].clone();
+        unresolved.resolve();
+        assert!(!unresolved.matches(&device));
+    }
+}
@@ -0,0 +1,380 @@
+use crate::config::Config;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+use std::process;
+
+// 检查结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Pass => write!(f, "通过"),
+            Status::Warn => write!(f, "警告"),
+            Status::Fail => write!(f, "失败"),
+        }
+    }
+}
+
+// 一项环境检查
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    // 未通过时的解决方法
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+// 运行所有检查
+pub fn run_checks() -> Vec<Check> {
+    let is_root = is_root();
+    vec![
+        check_display(
+            env::var("XDG_SESSION_TYPE").ok().as_deref(),
+            env::var_os("WAYLAND_DISPLAY").is_some(),
+            env::var_os("DISPLAY").is_some(),
+        ),
+        check_input_devices(Path::new("/dev/input"), is_root),
+        check_uinput(Path::new("/dev/uinput"), is_root),
+        check_groups(is_root),
+        check_other_instances(),
+        check_config(),
+    ]
+}
+
+// 输出检查结果，返回是否全部没有失败
+pub fn print_report(checks: &[Check]) -> bool {
+    for check in checks {
+        println!("[{}] {}: {}", check.status, check.name, check.detail);
+        if let Some(fix) = &check.fix {
+            println!("       解决方法: {}", fix);
+        }
+    }
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == Status::Fail)
+        .count();
+    let warned = checks
+        .iter()
+        .filter(|check| check.status == Status::Warn)
+        .count();
+    println!(
+        "共 {} 项检查，{} 项失败，{} 项警告",
+        checks.len(),
+        failed,
+        warned
+    );
+    failed == 0
+}
+
+// 显示服务器类型：rdev 后端依赖 X11
+fn check_display(session_type: Option<&str>, wayland: bool, x11: bool) -> Check {
+    const NAME: &str = "显示服务器";
+    if wayland || session_type == Some("wayland") {
+        let detail = if x11 {
+            "Wayland（XWayland 可用，但 rdev 无法拦截 Wayland 原生窗口的按键）"
+        } else {
+            "Wayland"
+        };
+        Check::warn(
+            NAME,
+            detail,
+            "使用 --backend evdev，直接从 /dev/input 读取按键并通过 uinput 注入",
+        )
+    } else if x11 {
+        Check::pass(NAME, "X11")
+    } else {
+        Check::warn(
+            NAME,
+            "没有检测到图形界面（控制台或 SSH 会话）",
+            "rdev 后端需要 X11，请使用 --backend evdev",
+        )
+    }
+}
+
+// 能否读取 /dev/input 下的事件设备（rdev grab 和 evdev 后端都需要）
+fn check_input_devices(dir: &Path, is_root: bool) -> Check {
+    const NAME: &str = "/dev/input 访问";
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Check::fail(
+                NAME,
+                format!("无法读取 {}: {}", dir.display(), e),
+                "确认系统提供了 evdev 设备（容器中需要挂载 /dev/input）",
+            )
+        }
+    };
+    let devices: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("event"))
+        })
+        .collect();
+    if devices.is_empty() {
+        return Check::fail(
+            NAME,
+            format!("{} 下没有事件设备", dir.display()),
+            "确认键盘鼠标已连接；容器中需要挂载 /dev/input",
+        );
+    }
+    let readable = devices
+        .iter()
+        .filter(|path| fs::File::open(path).is_ok())
+        .count();
+    if readable == 0 {
+        Check::fail(
+            NAME,
+            format!("{} 个事件设备都无法读取", devices.len()),
+            group_fix(is_root),
+        )
+    } else {
+        Check::pass(
+            NAME,
+            format!("可以读取 {}/{} 个事件设备", readable, devices.len()),
+        )
+    }
+}
+
+// 能否写入 /dev/uinput（rdev grab 和 evdev 后端用它重新发出按键）
+fn check_uinput(path: &Path, is_root: bool) -> Check {
+    const NAME: &str = "/dev/uinput 访问";
+    if !path.exists() {
+        return Check::fail(
+            NAME,
+            format!("{} 不存在", path.display()),
+            "运行 sudo modprobe uinput 加载内核模块（容器中需要挂载 /dev/uinput）",
+        );
+    }
+    match OpenOptions::new().write(true).open(path) {
+        Ok(_) => Check::pass(NAME, "可以写入"),
+        Err(e) => Check::fail(NAME, format!("无法写入: {}", e), group_fix(is_root)),
+    }
+}
+
+fn group_fix(is_root: bool) -> String {
+    if is_root {
+        "以 root 运行仍然无法访问，请检查设备文件权限或容器的设备挂载".to_string()
+    } else {
+        "运行 sudo usermod -aG input $USER 并重新登录，或以 root 运行；\
+         /dev/uinput 还需要 udev 规则 KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\""
+            .to_string()
+    }
+}
+
+// 当前用户是否在 input 用户组中
+fn check_groups(is_root: bool) -> Check {
+    const NAME: &str = "用户组";
+    if is_root {
+        return Check::pass(NAME, "以 root 运行");
+    }
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let group_file = fs::read_to_string("/etc/group").unwrap_or_default();
+    let groups = group_names(&process_groups(&status), &group_file);
+    if groups.iter().any(|group| group == "input") {
+        Check::pass(NAME, format!("属于 input 组（{}）", groups.join(", ")))
+    } else {
+        Check::warn(
+            NAME,
+            format!("不属于 input 组（当前: {}）", groups.join(", ")),
+            "运行 sudo usermod -aG input $USER 并重新登录",
+        )
+    }
+}
+
+fn is_root() -> bool {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| status_field(&status, "Uid:").map(|uid| uid.first() == Some(&0)))
+        .unwrap_or(false)
+}
+
+// /proc/self/status 中某一行的数字字段
+fn status_field(status: &str, field: &str) -> Option<Vec<u32>> {
+    let line = status.lines().find(|line| line.starts_with(field))?;
+    Some(
+        line[field.len()..]
+            .split_whitespace()
+            .filter_map(|id| id.parse().ok())
+            .collect(),
+    )
+}
+
+// 进程的主组和附加组
+fn process_groups(status: &str) -> Vec<u32> {
+    let mut gids = status_field(status, "Gid:")
+        .and_then(|gids| gids.first().copied())
+        .into_iter()
+        .collect::<Vec<_>>();
+    for gid in status_field(status, "Groups:").unwrap_or_default() {
+        if !gids.contains(&gid) {
+            gids.push(gid);
+        }
+    }
+    gids
+}
+
+// 按 /etc/group 把组 ID 转换为组名，找不到的保留数字
+fn group_names(gids: &[u32], group_file: &str) -> Vec<String> {
+    gids.iter()
+        .map(|gid| {
+            group_file
+                .lines()
+                .find_map(|line| {
+                    let mut fields = line.split(':');
+                    let name = fields.next()?;
+                    let id: u32 = fields.nth(1)?.parse().ok()?;
+                    (id == *gid).then(|| name.to_string())
+                })
+                .unwrap_or_else(|| gid.to_string())
+        })
+        .collect()
+}
+
+// 是否有其他实例在运行（两个实例同时拦截输入会互相干扰）
+fn check_other_instances() -> Check {
+    const NAME: &str = "其他实例";
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Check::pass(NAME, "无法读取 /proc，跳过检查");
+    };
+    let own_pid = process::id();
+    let name = env!("CARGO_PKG_NAME");
+    let others: Vec<u32> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&pid| pid != own_pid)
+        .filter(|pid| {
+            fs::read_to_string(format!("/proc/{}/comm", pid)).is_ok_and(|comm| comm.trim() == name)
+        })
+        .collect();
+    if others.is_empty() {
+        Check::pass(NAME, "没有其他实例在运行")
+    } else {
+        let pids: Vec<String> = others.iter().map(|pid| pid.to_string()).collect();
+        Check::fail(
+            NAME,
+            format!("其他实例正在运行（PID {}）", pids.join(", ")),
+            format!("先退出其他实例，例如 kill {}", pids.join(" ")),
+        )
+    }
+}
+
+// 配置文件能否找到并通过校验
+fn check_config() -> Check {
+    const NAME: &str = "配置文件";
+    let Some(path) = Config::find() else {
+        let expected = dirs::config_dir()
+            .map(|dir| {
+                dir.join("hero-rs")
+                    .join("config.toml")
+                    .display()
+                    .to_string()
+            })
+            .unwrap_or_else(|| "./config.toml".to_string());
+        return Check::warn(
+            NAME,
+            "没有找到配置文件，将使用内置默认配置",
+            format!("把 config.toml 复制到 {} 或当前目录", expected),
+        );
+    };
+    match Config::load(&path) {
+        Ok(config) => {
+            let champion_combos: usize = config
+                .champion_specific
+                .values()
+                .map(|champion| champion.combos.len())
+                .sum();
+            Check::pass(
+                NAME,
+                format!(
+                    "{}（{} 个全局连招，{} 个英雄的 {} 个连招）",
+                    path.display(),
+                    config.global_combos.len(),
+                    config.champion_specific.len(),
+                    champion_combos
+                ),
+            )
+        }
+        Err(e) => Check::fail(NAME, e, "按错误信息修正配置文件，格式参考 README"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_and_groups() {
+        assert_eq!(check_display(Some("x11"), false, true).status, Status::Pass);
+        assert_eq!(
+            check_display(Some("wayland"), true, true).status,
+            Status::Warn
+        );
+        assert_eq!(check_display(None, false, false).status, Status::Warn);
+
+        let status = "Name:\thero-rs\nUid:\t1000\t1000\t1000\t1000\nGid:\t1000\t1000\t1000\t1000\nGroups:\t4 104 1000 \n";
+        let group_file = "root:x:0:\nadm:x:4:user\ninput:x:104:user\nuser:x:1000:\n";
+        assert_eq!(process_groups(status), [1000, 4, 104]);
+        assert_eq!(
+            group_names(&process_groups(status), group_file),
+            ["user", "adm", "input"]
+        );
+        assert_eq!(group_names(&[42], group_file), ["42"]);
+    }
+
+    #[test]
+    fn test_missing_devices() {
+        let dir = env::temp_dir().join(format!("hero-rs-doctor-{}", process::id()));
+        assert_eq!(check_input_devices(&dir, false).status, Status::Fail);
+        assert_eq!(
+            check_uinput(&dir.join("uinput"), false).status,
+            Status::Fail
+        );
+
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(check_input_devices(&dir, false).status, Status::Fail);
+        fs::write(dir.join("event0"), "").unwrap();
+        assert_eq!(check_input_devices(&dir, false).status, Status::Pass);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
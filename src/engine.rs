@@ -0,0 +1,830 @@
+// 处理输入事件的核心：按键历史、触发索引、连招条件和判定，不依赖命令行界面，
+// 命令行程序和性能测试都通过 Engine 处理输入
+use crate::clock::{self, Clock};
+use crate::config::{ActionKey, ComboConfig, Conditions, Config, TriggerType};
+use crate::events::{AppEvent, EventBus};
+use crate::index::TriggerIndex;
+use crate::input::{DeviceInfo, InputError, InputSink};
+use crate::pattern::key_name;
+use crate::{debug, info, warn};
+use rdev::{Event, EventType, Key};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+// 定义操作类型：按下或释放
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionType {
+    Press,
+    Release,
+}
+
+// 定义一个操作，包含按键和操作类型
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action {
+    pub key: ActionKey,
+    pub action_type: ActionType,
+    pub timestamp: Instant,
+    pub device: Option<Arc<DeviceInfo>>, // 事件来自的设备，后端无法区分设备时为 None
+}
+
+// 定义连招序列
+#[derive(Debug, Clone)]
+pub struct Combo {
+    pub name: String,
+    pub sequence: Vec<ActionKey>,
+    pub delays: Vec<(u64, u64)>, // (按下前延迟, 按下后延迟)
+    pub trigger: TriggerType,
+    pub block_original_input: bool,
+    pub active: bool,
+    pub conditions: Conditions,
+    pub hold_ms: u64, // 模拟按键时按下与释放之间的间隔
+    pub priority: i32,
+    pub specificity: usize, // 触发条件的具体程度，优先级相同时更具体的胜出
+}
+
+impl Combo {
+    pub fn from_config(config: &ComboConfig, hold_ms: u64) -> Self {
+        Combo {
+            name: config.name.clone(),
+            sequence: config
+                .sequence
+                .iter()
+                .map(|step| step.key.clone())
+                .collect(),
+            delays: config
+                .sequence
+                .iter()
+                .map(|step| (step.delay_before_ms, step.delay_after_ms))
+                .collect(),
+            trigger: config.trigger.clone(),
+            block_original_input: config.block_original_input,
+            active: config.active,
+            conditions: config.conditions.clone(),
+            hold_ms,
+            priority: config.priority,
+            specificity: config.trigger.specificity(),
+        }
+    }
+
+    // 执行一次连招需要的总时间
+    pub fn duration(&self) -> Duration {
+        let steps: u64 = self
+            .delays
+            .iter()
+            .map(|(before, after)| before + after)
+            .sum();
+        Duration::from_millis(steps + self.hold_ms * self.sequence.len() as u64)
+    }
+
+    // 执行连招，通过 sink 注入按键，每个步骤完成后发布到 events
+    pub fn execute(
+        &self,
+        sink: &dyn InputSink,
+        clock: &dyn Clock,
+        events: &EventBus,
+    ) -> Result<(), InputError> {
+        for (idx, key) in self.sequence.iter().enumerate() {
+            // 获取当前按键的延迟配置
+            let (before_delay, after_delay) = if idx < self.delays.len() {
+                self.delays[idx]
+            } else {
+                (0, 0)
+            };
+
+            // 执行前延迟
+            if before_delay > 0 {
+                clock.sleep(Duration::from_millis(before_delay));
+            }
+
+            // 按下按键
+            match key {
+                ActionKey::Keyboard(k) => {
+                    sink.send(&EventType::KeyPress(*k))?;
+                    clock.sleep(Duration::from_millis(self.hold_ms)); // 短暂延迟确保按键被识别
+                    sink.send(&EventType::KeyRelease(*k))?;
+                }
+                ActionKey::Mouse(b) => {
+                    sink.send(&EventType::ButtonPress(*b))?;
+                    clock.sleep(Duration::from_millis(self.hold_ms)); // 短暂延迟确保按键被识别
+                    sink.send(&EventType::ButtonRelease(*b))?;
+                }
+            }
+            events.publish_with(|| AppEvent::ComboStepExecuted {
+                combo: self.name.clone(),
+                step: idx + 1,
+                key: key.name(),
+            });
+
+            // 执行后延迟
+            if after_delay > 0 {
+                clock.sleep(Duration::from_millis(after_delay));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// 连招条件不满足的原因
+#[derive(Debug, Clone)]
+pub enum ConditionFailure {
+    Paused,
+    ChatOpen,
+    NotHeld(ActionKey),
+    Held(ActionKey),
+    Champion,
+    Layer,
+    Device(Option<Arc<DeviceInfo>>),
+}
+
+impl fmt::Display for ConditionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConditionFailure::Paused => write!(f, "连招已暂停"),
+            ConditionFailure::ChatOpen => write!(f, "聊天框已打开"),
+            ConditionFailure::NotHeld(key) => write!(f, "{:?} 未按住", key),
+            ConditionFailure::Held(key) => write!(f, "{:?} 处于按下状态", key),
+            ConditionFailure::Champion => write!(f, "当前英雄不符合"),
+            ConditionFailure::Layer => write!(f, "所需的层未激活"),
+            ConditionFailure::Device(Some(device)) => write!(f, "按键来自不符合的设备 {}", device),
+            ConditionFailure::Device(None) => write!(f, "当前输入后端无法区分设备"),
+        }
+    }
+}
+
+// 候选连招的判定结果
+#[derive(Debug, Clone)]
+pub enum Verdict {
+    Inactive,
+    ConditionFailed(ConditionFailure),
+    ModifierNotHeld(Key),
+    Matched,
+}
+
+// 一次按键中某个候选连招的判定记录
+#[derive(Debug, Clone)]
+pub struct CandidateReport {
+    pub combo: usize,
+    pub verdict: Verdict,
+}
+
+// 一次按键的匹配结果，供 why 命令解释
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub time: Option<Instant>,
+    pub reports: Vec<CandidateReport>,
+    pub fired: Vec<usize>,
+}
+
+// 按键历史记录
+pub struct KeyHistory {
+    actions: VecDeque<Action>,
+    pressed_keys: HashMap<ActionKey, Instant>,
+    recent_keys: VecDeque<(Key, Instant)>,
+    max_size: usize,
+    sequence_max_size: usize,
+    history_timeout: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl KeyHistory {
+    pub fn new(
+        max_size: usize,
+        sequence_max_size: usize,
+        history_timeout_ms: u64,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        KeyHistory {
+            actions: VecDeque::with_capacity(max_size),
+            pressed_keys: HashMap::new(),
+            recent_keys: VecDeque::with_capacity(sequence_max_size),
+            max_size,
+            sequence_max_size,
+            history_timeout: Duration::from_millis(history_timeout_ms),
+            clock,
+        }
+    }
+
+    pub fn add_action(&mut self, action: Action) {
+        // 更新按下的键
+        if action.action_type == ActionType::Press {
+            self.pressed_keys
+                .insert(action.key.clone(), action.timestamp);
+        } else if action.action_type == ActionType::Release {
+            self.pressed_keys.remove(&action.key);
+        }
+
+        // 如果是键盘按键，添加到最近按下的键序列中
+        if let ActionKey::Keyboard(key) = &action.key {
+            if action.action_type == ActionType::Press {
+                self.recent_keys.push_back((*key, action.timestamp));
+
+                // 保持最近按键序列不超过最大大小
+                if self.recent_keys.len() > self.sequence_max_size {
+                    self.recent_keys.pop_front();
+                }
+            }
+        }
+
+        // 移除超时的操作
+        let now = self.clock.now();
+        while let Some(front) = self.actions.front() {
+            if now.duration_since(front.timestamp) > self.history_timeout {
+                self.actions.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        // 添加新操作
+        self.actions.push_back(action);
+
+        // 如果超出最大大小，移除最旧的
+        if self.actions.len() > self.max_size {
+            self.actions.pop_front();
+        }
+    }
+
+    // 最近一个事件来自的设备
+    pub fn current_device(&self) -> Option<&Arc<DeviceInfo>> {
+        self.actions
+            .back()
+            .and_then(|action| action.device.as_ref())
+    }
+
+    // 获取最近按下的按键序列
+    pub fn recent_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.recent_keys.iter().map(|(key, _)| *key)
+    }
+
+    // 检查是否匹配按键修饰符组合
+    pub fn matches_key_modifier(&self, modifier: Key, key: Key) -> bool {
+        // 检查修饰键是否已按下
+        let modifier_pressed = self
+            .pressed_keys
+            .contains_key(&ActionKey::Keyboard(modifier));
+
+        // 检查主键是否最近按下
+        if let Some((last_key, _)) = self.recent_keys.back() {
+            return modifier_pressed && *last_key == key;
+        }
+
+        false
+    }
+
+    // 检查按键或鼠标按钮当前是否处于按下状态
+    pub fn is_pressed(&self, key: &ActionKey) -> bool {
+        self.pressed_keys.contains_key(key)
+    }
+}
+
+// 处理一个输入事件的结果，由调用者执行后续的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Blocked,
+    // 按下了切换英雄的快捷键（事件放行），None 表示切换到全局配置
+    SwitchChampion(Option<&'static str>),
+    // 触发了连招，触发的连招记录在 resolutions[key].fired 中；blocked 表示原始按键被屏蔽
+    Fired { key: Key, blocked: bool },
+}
+
+impl Outcome {
+    // 是否把原始事件放行给游戏
+    pub fn passes(&self) -> bool {
+        !matches!(
+            self,
+            Outcome::Blocked | Outcome::Fired { blocked: true, .. }
+        )
+    }
+}
+
+pub struct Engine {
+    pub config: Config,
+    pub history: KeyHistory,
+    pub combos: Vec<Combo>,
+    pub index: TriggerIndex,
+    blocked_keys: HashMap<Key, Instant>,
+    pub current_champion: Option<String>,
+    pub paused: bool,
+    pub chat_open: bool,
+    pub layers: HashSet<String>,
+    pub resolutions: HashMap<Key, Resolution>, // 每个按键最近一次的匹配结果
+    pub clock: Arc<dyn Clock>,
+    pub dry_run: bool,                  // 演习模式：照常判定，但不屏蔽原始按键
+    pub bindings: HashMap<Key, String>, // 按键绑定的命令
+    pub command_tx: Option<mpsc::Sender<String>>, // 按下绑定的按键时把命令发给命令线程执行
+    pub events: Arc<EventBus>,          // 输入事件和屏蔽的按键发布到这里
+}
+
+impl Engine {
+    pub fn new(config: Config, clock: Arc<dyn Clock>, events: Arc<EventBus>) -> Self {
+        // 创建按键历史
+        let history = KeyHistory::new(
+            config.general.history_size,
+            10, // 按键序列最大长度
+            config.general.history_timeout_ms,
+            Arc::clone(&clock),
+        );
+        Engine {
+            config,
+            history,
+            combos: Vec::new(),
+            index: TriggerIndex::build([]),
+            blocked_keys: HashMap::new(),
+            current_champion: None,
+            paused: false,
+            chat_open: false,
+            layers: HashSet::new(),
+            resolutions: HashMap::new(),
+            clock,
+            dry_run: false,
+            bindings: HashMap::new(),
+            command_tx: None,
+            events,
+        }
+    }
+
+    // 替换加载的连招
+    pub fn set_combos(&mut self, combos: Vec<Combo>) {
+        self.combos = combos;
+        // 连招变化后重建触发索引，旧的匹配记录不再对应
+        self.index = TriggerIndex::build(self.combos.iter().map(|combo| &combo.trigger));
+        self.resolutions.clear();
+    }
+
+    // 检查当前英雄是否与给定名称匹配（配置键名或显示名称）
+    pub fn is_current_champion(&self, name: &str) -> bool {
+        let Some(current) = &self.current_champion else {
+            return false;
+        };
+
+        if current.eq_ignore_ascii_case(name) {
+            return true;
+        }
+
+        match self.config.find_champion(current) {
+            Some((id, champion)) => {
+                id.eq_ignore_ascii_case(name) || champion.name.eq_ignore_ascii_case(name)
+            }
+            None => false,
+        }
+    }
+
+    // 检查连招的附加条件是否全部满足
+    pub fn check_conditions(&self, conditions: &Conditions) -> Result<(), ConditionFailure> {
+        if self.paused && !conditions.allow_when_paused {
+            return Err(ConditionFailure::Paused);
+        }
+
+        if self.chat_open && !conditions.allow_in_chat {
+            return Err(ConditionFailure::ChatOpen);
+        }
+
+        if let Some(key) = conditions
+            .held
+            .iter()
+            .find(|key| !self.history.is_pressed(key))
+        {
+            return Err(ConditionFailure::NotHeld(key.clone()));
+        }
+
+        if let Some(key) = conditions
+            .not_held
+            .iter()
+            .find(|key| self.history.is_pressed(key))
+        {
+            return Err(ConditionFailure::Held(key.clone()));
+        }
+
+        if !conditions.champions.is_empty()
+            && !conditions
+                .champions
+                .iter()
+                .any(|name| self.is_current_champion(name))
+        {
+            return Err(ConditionFailure::Champion);
+        }
+
+        if !conditions.layers.is_empty()
+            && !conditions
+                .layers
+                .iter()
+                .any(|layer| self.layers.contains(layer))
+        {
+            return Err(ConditionFailure::Layer);
+        }
+
+        // 连招没有指定设备时使用全局的设备限制
+        let filters = if conditions.devices.is_empty() {
+            &self.config.general.devices
+        } else {
+            &conditions.devices
+        };
+        if !filters.is_empty() {
+            let device = self.history.current_device();
+            if !device.is_some_and(|device| filters.iter().any(|filter| filter.matches(device))) {
+                return Err(ConditionFailure::Device(device.cloned()));
+            }
+        }
+
+        Ok(())
+    }
+
+    // 判定候选连招并决定触发哪些：优先级高者胜出，其次是更具体的触发条件，最后按配置顺序
+    pub fn resolve(&self, key: Key, resolution: &mut Resolution) {
+        resolution.reports.clear();
+        resolution.fired.clear();
+
+        for &idx in self.index.candidates() {
+            let combo = &self.combos[idx];
+            let verdict = if !combo.active {
+                Verdict::Inactive
+            } else if let Err(failure) = self.check_conditions(&combo.conditions) {
+                Verdict::ConditionFailed(failure)
+            } else {
+                match &combo.trigger {
+                    TriggerType::KeyModifier { modifier, .. }
+                        if !self.history.matches_key_modifier(*modifier, key) =>
+                    {
+                        Verdict::ModifierNotHeld(*modifier)
+                    }
+                    _ => Verdict::Matched,
+                }
+            };
+
+            if let Verdict::Matched = verdict {
+                resolution.fired.push(idx);
+            }
+            resolution.reports.push(CandidateReport {
+                combo: idx,
+                verdict,
+            });
+        }
+
+        resolution.fired.sort_by_key(|&idx| {
+            let combo = &self.combos[idx];
+            (
+                std::cmp::Reverse(combo.priority),
+                std::cmp::Reverse(combo.specificity),
+                idx,
+            )
+        });
+        if !self.config.general.fire_all_matches {
+            resolution.fired.truncate(1);
+        }
+    }
+
+    // 检查按键是否被屏蔽
+    pub fn is_key_blocked(&mut self, key: Key) -> bool {
+        // 清理过期的屏蔽
+        let now = self.clock.now();
+        self.blocked_keys.retain(|_, time| {
+            now.duration_since(*time) < Duration::from_millis(500) // 按键屏蔽最多持续500毫秒
+        });
+
+        self.blocked_keys.contains_key(&key)
+    }
+
+    // 屏蔽按键
+    pub fn block_key(&mut self, key: Key) {
+        self.events
+            .publish_with(|| AppEvent::KeyBlocked { key: key_name(key) });
+        self.blocked_keys.insert(key, self.clock.now());
+    }
+
+    // 事件实际发生的时间：使用事件自带的时间戳换算到单调时钟，而不是处理事件的时间
+    // 这样输入线程繁忙时不会把按键间隔拉长，导致序列触发超时
+    fn event_time(&self, event: &Event) -> Instant {
+        let (time, lag) = clock::to_monotonic(self.clock.as_ref(), event.time);
+        if lag.as_millis() as u64 > self.config.general.lag_warning_ms {
+            warn!(
+                "警告: 输入事件延迟 {} 毫秒才被处理: {:?}",
+                lag.as_millis(),
+                event.event_type
+            );
+        }
+        time
+    }
+
+    // 处理一个输入事件，结果发布到事件总线，日志和统计在订阅者中完成
+    pub fn handle_event(&mut self, event: &Event, device: Option<&Arc<DeviceInfo>>) -> Outcome {
+        let outcome = self.process_event(event, device);
+        if !matches!(
+            event.event_type,
+            EventType::MouseMove { .. } | EventType::Wheel { .. }
+        ) {
+            self.events.publish_with(|| AppEvent::InputObserved {
+                input: event.event_type,
+                device: device.cloned(),
+                blocked: !outcome.passes(),
+            });
+        }
+        outcome
+    }
+
+    fn process_event(&mut self, event: &Event, device: Option<&Arc<DeviceInfo>>) -> Outcome {
+        let (key, action_type) = match event.event_type {
+            EventType::KeyPress(key) => (ActionKey::Keyboard(key), ActionType::Press),
+            EventType::KeyRelease(key) => (ActionKey::Keyboard(key), ActionType::Release),
+            EventType::ButtonPress(button) => (ActionKey::Mouse(button), ActionType::Press),
+            EventType::ButtonRelease(button) => (ActionKey::Mouse(button), ActionType::Release),
+            _ => return Outcome::Passed,
+        };
+        // 检查按键是否被屏蔽
+        if let ActionKey::Keyboard(key) = key {
+            if self.is_key_blocked(key) {
+                return Outcome::Blocked;
+            }
+        }
+
+        let action = Action {
+            key,
+            action_type,
+            timestamp: self.event_time(event),
+            device: device.cloned(),
+        };
+        let EventType::KeyPress(key) = event.event_type else {
+            self.history.add_action(action);
+            return Outcome::Passed;
+        };
+
+        // 推进触发索引，得到按键模式匹配的候选连招
+        let timestamp = action.timestamp;
+        self.index.dispatch(key, timestamp);
+
+        // 更新按键历史
+        self.history.add_action(action);
+
+        // 跟踪聊天框状态：回车打开/发送，Esc关闭
+        match key {
+            Key::Return => self.chat_open = !self.chat_open,
+            Key::Escape => self.chat_open = false,
+            _ => {}
+        }
+
+        // 绑定了命令的按键：交给命令线程执行，暂停时也有效（用于暂停/恢复的快捷键）
+        if !self.chat_open {
+            if let (Some(command), Some(tx)) = (self.bindings.get(&key), &self.command_tx) {
+                debug!("按键 {:?} 执行命令: {}", key, command);
+                let _ = tx.send(command.clone());
+                return Outcome::Passed;
+            }
+        }
+
+        // 检查快捷键 - 切换英雄
+        // Shift+Y 切换到亚索
+        if key == Key::KeyY && self.history.matches_key_modifier(Key::ShiftLeft, Key::KeyY) {
+            return Outcome::SwitchChampion(Some("亚索"));
+        }
+
+        // Shift+S 切换到无英雄（全局配置）
+        if key == Key::KeyS && self.history.matches_key_modifier(Key::ShiftLeft, Key::KeyS) {
+            return Outcome::SwitchChampion(None);
+        }
+
+        // 判定候选连招，记录结果供 why 命令使用
+        let mut resolution = self.resolutions.remove(&key).unwrap_or_default();
+        resolution.time = Some(timestamp);
+        self.resolve(key, &mut resolution);
+
+        // 需要屏蔽原始输入的连招屏蔽这个按键，连招由调用者执行
+        for &idx in &resolution.fired {
+            let combo = &self.combos[idx];
+            if !combo.block_original_input {
+                continue;
+            }
+            if self.dry_run {
+                info!(
+                    "[dry-run] 连招 {} 会屏蔽原始按键 {:?}（演习模式下放行）",
+                    combo.name, key
+                );
+            } else {
+                self.block_key(key);
+            }
+        }
+        let fired = !resolution.fired.is_empty();
+        self.resolutions.insert(key, resolution);
+
+        if fired {
+            Outcome::Fired {
+                key,
+                blocked: self.is_key_blocked(key),
+            }
+        } else {
+            Outcome::Passed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{ManualClock, SystemClock};
+    use crate::input::MockSink;
+
+    // 加载配置中的全局连招
+    fn engine(config: Config, clock: Arc<dyn Clock>) -> Engine {
+        let hold_ms = config.general.default_delay_ms;
+        let combos = config
+            .global_combos
+            .iter()
+            .map(|combo| Combo::from_config(combo, hold_ms))
+            .collect();
+        let mut engine = Engine::new(config, clock, Arc::new(EventBus::new()));
+        engine.set_combos(combos);
+        engine
+    }
+
+    #[test]
+    fn test_key_history() {
+        let mut history = KeyHistory::new(5, 5, 1000, Arc::new(SystemClock));
+        let now = Instant::now();
+
+        // 添加一些按键
+        history.add_action(Action {
+            key: ActionKey::Keyboard(Key::KeyQ),
+            action_type: ActionType::Press,
+            timestamp: now,
+            device: None,
+        });
+
+        history.add_action(Action {
+            key: ActionKey::Keyboard(Key::KeyW),
+            action_type: ActionType::Press,
+            timestamp: now,
+            device: None,
+        });
+
+        history.add_action(Action {
+            key: ActionKey::Keyboard(Key::KeyE),
+            action_type: ActionType::Press,
+            timestamp: now,
+            device: None,
+        });
+
+        // 测试按键序列
+        let recent_keys: Vec<Key> = history.recent_keys().collect();
+        assert_eq!(recent_keys.len(), 3);
+        assert_eq!(recent_keys[0], Key::KeyQ);
+        assert_eq!(recent_keys[1], Key::KeyW);
+        assert_eq!(recent_keys[2], Key::KeyE);
+    }
+
+    #[test]
+    fn test_history_timeout_boundary() {
+        let clock = ManualClock::new();
+        let mut history = KeyHistory::new(10, 10, 1000, Arc::new(clock.clone()));
+        let mut press = |key| {
+            history.add_action(Action {
+                key: ActionKey::Keyboard(key),
+                action_type: ActionType::Press,
+                timestamp: clock.now(),
+                device: None,
+            });
+            history
+                .actions
+                .iter()
+                .map(|action| action.key.clone())
+                .collect::<Vec<_>>()
+        };
+
+        press(Key::KeyQ);
+        clock.advance_ms(1000);
+        // 恰好 1000ms 时仍然保留
+        assert_eq!(
+            press(Key::KeyW),
+            [
+                ActionKey::Keyboard(Key::KeyQ),
+                ActionKey::Keyboard(Key::KeyW)
+            ]
+        );
+        clock.advance_ms(1);
+        assert_eq!(
+            press(Key::KeyE),
+            [
+                ActionKey::Keyboard(Key::KeyW),
+                ActionKey::Keyboard(Key::KeyE)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_sequence_interleaved() {
+        let now = Instant::now();
+        let presses = [(Key::KeyE, 0), (Key::KeyW, 40), (Key::KeyR, 80)];
+        let matches = |keys: &[Key], timeout_ms, max_interleaved, ignore_keys: &[Key]| {
+            let automaton =
+                crate::pattern::Automaton::sequence(keys, timeout_ms, max_interleaved, ignore_keys);
+            let mut matcher = crate::pattern::Matcher::new(Arc::new(automaton));
+            presses
+                .iter()
+                .map(|&(key, offset)| matcher.advance(key, now + Duration::from_millis(offset)))
+                .last()
+                .unwrap()
+        };
+        let sequence = [Key::KeyE, Key::KeyR];
+
+        // 严格匹配时，中间的W导致不匹配
+        assert!(!matches(&sequence, 150, 0, &[]));
+        // 允许一个无关按键或忽略W时匹配
+        assert!(matches(&sequence, 150, 1, &[]));
+        assert!(matches(&sequence, 150, 0, &[Key::KeyW]));
+        // 仍然受超时限制
+        assert!(!matches(&sequence, 50, 1, &[]));
+        // 最后一个按键必须是刚按下的键
+        assert!(!matches(&[Key::KeyE, Key::KeyW], 150, 1, &[]));
+    }
+
+    #[test]
+    fn test_match_resolution() {
+        let config = |single_priority: i32, fire_all: bool| {
+            Config::parse(&format!(
+                r#"
+                general = {{ fire_all_matches = {} }}
+                [[global_combos]]
+                name = "单键R"
+                sequence = [{{ key = {{ Keyboard = "KeyQ" }} }}]
+                trigger = {{ type = "SingleKey", key = "KeyR" }}
+                priority = {}
+                [[global_combos]]
+                name = "序列ER"
+                sequence = [{{ key = {{ Keyboard = "KeyQ" }} }}]
+                trigger = {{ type = "KeySequence", keys = ["KeyE", "KeyR"], timeout_ms = 150 }}
+                "#,
+                fire_all, single_priority
+            ))
+            .unwrap()
+        };
+        let fired = |config: Config| {
+            let mut engine = engine(config, Arc::new(SystemClock));
+            let now = Instant::now();
+            engine.index.dispatch(Key::KeyE, now);
+            engine
+                .index
+                .dispatch(Key::KeyR, now + Duration::from_millis(50));
+            let mut resolution = Resolution::default();
+            engine.resolve(Key::KeyR, &mut resolution);
+            resolution.fired
+        };
+
+        // 优先级相同时，更具体的序列胜出
+        assert_eq!(fired(config(0, false)), [1]);
+        // 优先级更高的单键胜出
+        assert_eq!(fired(config(1, false)), [0]);
+        // 触发全部匹配，按优先级排序
+        assert_eq!(fired(config(0, true)), [1, 0]);
+    }
+
+    #[test]
+    fn test_block_expiry_boundary() {
+        let clock = ManualClock::new();
+        let mut engine = engine(Config::builtin(), Arc::new(clock.clone()));
+        engine.block_key(Key::KeyR);
+
+        clock.advance_ms(499);
+        assert!(engine.is_key_blocked(Key::KeyR));
+        clock.advance_ms(1);
+        assert!(!engine.is_key_blocked(Key::KeyR));
+    }
+
+    #[test]
+    fn test_combo_execute_timing() {
+        let config = Config::parse(
+            r#"
+            [[global_combos]]
+            name = "QW"
+            sequence = [
+                { key = { Keyboard = "KeyQ" }, delay_after_ms = 50 },
+                { key = { Keyboard = "KeyW" }, delay_before_ms = 20 },
+            ]
+            trigger = { type = "SingleKey", key = "KeyA" }
+            "#,
+        )
+        .unwrap();
+        let clock = ManualClock::new();
+        let sink = MockSink::with_clock(Arc::new(clock.clone()));
+        let start = clock.now();
+        Combo::from_config(&config.global_combos[0], 10)
+            .execute(&sink, &clock, &EventBus::new())
+            .unwrap();
+
+        let timeline: Vec<(u128, EventType)> = sink
+            .events()
+            .into_iter()
+            .map(|(time, event)| (time.duration_since(start).as_millis(), event))
+            .collect();
+        assert_eq!(
+            timeline,
+            [
+                (0, EventType::KeyPress(Key::KeyQ)),
+                (10, EventType::KeyRelease(Key::KeyQ)),
+                (80, EventType::KeyPress(Key::KeyW)),
+                (90, EventType::KeyRelease(Key::KeyW)),
+            ]
+        );
+        assert_eq!(clock.elapsed(), Duration::from_millis(90));
+    }
+}
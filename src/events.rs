@@ -0,0 +1,236 @@
+// 程序内部的事件总线：处理输入和状态变化时发布事件，日志、统计、控制接口和事件流各自订阅，
+// 处理输入的代码不需要知道有哪些订阅者
+use crate::input::DeviceInfo;
+use rdev::EventType;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 每个订阅者最多缓存的事件数，订阅者处理不过来时丢弃新事件，不阻塞发布者
+const QUEUE_SIZE: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppEvent {
+    // 处理完的按键和鼠标按键事件（不包括鼠标移动和滚轮），blocked 表示没有放行给游戏
+    // 每个按键都会发布，设备共用输入源中的同一份信息，不复制名称；序列化时只有设备名称
+    InputObserved {
+        input: EventType,
+        #[serde(serialize_with = "serialize_device_name")]
+        device: Option<Arc<DeviceInfo>>,
+        blocked: bool,
+    },
+    // 连招被触发，key 是触发的按键，手动执行时为 None
+    ComboTriggered {
+        combo: String,
+        key: Option<String>,
+    },
+    // 倒计时结束，开始注入按键
+    ComboStarted {
+        combo: String,
+    },
+    // 注入了连招的一个步骤，step 从 1 开始
+    ComboStepExecuted {
+        combo: String,
+        step: usize,
+        key: String,
+    },
+    ComboCompleted {
+        combo: String,
+    },
+    // 连招没有执行完，例如注入按键失败
+    ComboCancelled {
+        combo: String,
+        reason: String,
+    },
+    // 切换英雄，None 表示使用全局连招
+    ChampionSwitched {
+        champion: Option<String>,
+    },
+    PauseToggled {
+        paused: bool,
+    },
+    // 原始按键被连招屏蔽
+    KeyBlocked {
+        key: String,
+    },
+}
+
+fn serialize_device_name<S: Serializer>(
+    device: &Option<Arc<DeviceInfo>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    device
+        .as_ref()
+        .map(|device| device.name.as_str())
+        .serialize(serializer)
+}
+
+impl AppEvent {
+    // 每个按键都会发布的事件，透露输入的内容
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            AppEvent::InputObserved { .. } | AppEvent::KeyBlocked { .. }
+        )
+    }
+}
+
+// 发布的事件，附带发布时的 Unix 时间戳（毫秒）
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Envelope {
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub event: AppEvent,
+}
+
+// 同步监听者，在发布者的线程中调用，只适合很快的处理（例如计数）
+// 监听者中不能再调用 listen，否则会死锁
+pub type Listener = dyn Fn(&AppEvent) + Send + Sync;
+
+#[derive(Default)]
+pub struct EventBus {
+    listeners: RwLock<Vec<Box<Listener>>>,
+    subscribers: Mutex<Vec<SyncSender<Envelope>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 发布事件，不会阻塞；已经断开的订阅者会被移除
+    pub fn publish(&self, event: AppEvent) {
+        self.publish_with(|| event);
+    }
+
+    // 有监听者或订阅者时才构造事件，用于处理每个输入事件的热路径
+    pub fn publish_with(&self, event: impl FnOnce() -> AppEvent) {
+        let listeners = self.listeners.read().unwrap();
+        if listeners.is_empty() && self.subscribers.lock().unwrap().is_empty() {
+            return;
+        }
+        let event = event();
+        for listener in listeners.iter() {
+            listener(&event);
+        }
+        drop(listeners);
+
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+        let envelope = Envelope {
+            timestamp_ms,
+            event,
+        };
+        subscribers.retain(|subscriber| match subscriber.try_send(envelope.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+
+    // 订阅之后发布的事件，丢弃接收端即取消订阅
+    pub fn subscribe(&self) -> Receiver<Envelope> {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    // 添加同步监听者，不会错过任何事件
+    pub fn listen(&self, listener: Box<Listener>) {
+        self.listeners.write().unwrap().push(listener);
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("listeners", &self.listeners.read().unwrap().len())
+            .field("subscribers", &self.subscribers.lock().unwrap().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdev::Key;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_publish_subscribe() {
+        let bus = EventBus::new();
+        // 没有订阅者时直接丢弃
+        bus.publish(AppEvent::PauseToggled { paused: true });
+
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+        bus.publish(AppEvent::ComboTriggered {
+            combo: "QWE连招".to_string(),
+            key: Some("A".to_string()),
+        });
+        let envelope = first.try_recv().unwrap();
+        assert_eq!(envelope, second.try_recv().unwrap());
+        assert!(first.try_recv().is_err());
+
+        let json = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(json["type"], "combo_triggered");
+        assert_eq!(json["combo"], "QWE连招");
+        assert_eq!(json["key"], "A");
+        assert!(json["timestamp_ms"].as_u64().unwrap() > 0);
+
+        // 取消订阅后不再投递；队列满时丢弃新事件
+        drop(second);
+        for _ in 0..QUEUE_SIZE + 10 {
+            bus.publish(AppEvent::PauseToggled { paused: false });
+        }
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+        assert_eq!(first.try_iter().count(), QUEUE_SIZE);
+    }
+
+    #[test]
+    fn test_listen() {
+        let bus = EventBus::new();
+        let built = AtomicUsize::new(0);
+        let event = || {
+            built.fetch_add(1, Ordering::Relaxed);
+            AppEvent::InputObserved {
+                input: EventType::KeyPress(Key::KeyQ),
+                device: Some(Arc::new(DeviceInfo {
+                    path: "/dev/input/event3".into(),
+                    name: "键盘".to_string(),
+                    vendor: 1,
+                    product: 2,
+                })),
+                blocked: true,
+            }
+        };
+        // 没有人关心时不构造事件
+        bus.publish_with(event);
+        assert_eq!(built.load(Ordering::Relaxed), 0);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let listener = Arc::clone(&seen);
+        bus.listen(Box::new(move |event| {
+            listener.lock().unwrap().push(event.clone())
+        }));
+        bus.publish_with(event);
+        assert_eq!(built.load(Ordering::Relaxed), 1);
+        let seen = seen.lock().unwrap();
+        assert!(matches!(
+            seen[..],
+            [AppEvent::InputObserved { blocked: true, .. }]
+        ));
+
+        let json = serde_json::to_value(&seen[0]).unwrap();
+        assert_eq!(json["type"], "input_observed");
+        assert_eq!(json["input"], serde_json::json!({ "KeyPress": "KeyQ" }));
+        assert_eq!(json["device"], "键盘");
+    }
+}
@@ -0,0 +1,125 @@
+// 名称的模糊匹配，用于在命令中按名称引用连招和英雄
+// 不区分大小写并忽略空白；中文名称按字匹配
+
+// 匹配程度，越靠后越精确
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    // 按顺序包含输入的所有字符，例如 "亚索r" 匹配 "亚索E+R触发QR"
+    Subsequence,
+    Substring,
+    Prefix,
+    Exact,
+}
+
+// 按名称查找的结果，给出名称在候选中的下标
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup {
+    Found(usize),
+    // 多个候选同样匹配
+    Ambiguous(Vec<usize>),
+    NotFound,
+}
+
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+pub fn match_kind(query: &str, candidate: &str) -> Option<MatchKind> {
+    let query = normalize(query);
+    let candidate = normalize(candidate);
+    if query.is_empty() {
+        return None;
+    }
+    if candidate == query {
+        Some(MatchKind::Exact)
+    } else if candidate.starts_with(&query) {
+        Some(MatchKind::Prefix)
+    } else if candidate.contains(&query) {
+        Some(MatchKind::Substring)
+    } else {
+        let mut chars = candidate.chars();
+        query
+            .chars()
+            .all(|c| chars.any(|candidate_char| candidate_char == c))
+            .then_some(MatchKind::Subsequence)
+    }
+}
+
+// 找出最精确匹配的候选，最精确的匹配不止一个时返回全部
+pub fn lookup<'a>(query: &str, names: impl IntoIterator<Item = &'a str>) -> Lookup {
+    let mut best = None;
+    let mut matches = Vec::new();
+    for (i, name) in names.into_iter().enumerate() {
+        let kind = match_kind(query, name);
+        if kind.is_none() || kind < best {
+            continue;
+        }
+        if kind > best {
+            best = kind;
+            matches.clear();
+        }
+        matches.push(i);
+    }
+    match matches.as_slice() {
+        [] => Lookup::NotFound,
+        [i] => Lookup::Found(*i),
+        _ => Lookup::Ambiguous(matches),
+    }
+}
+
+// 补全候选：以输入开头的名称，没有时退回到模糊匹配
+pub fn complete<'a>(prefix: &str, names: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let names: Vec<&str> = names.into_iter().collect();
+    let normalized = normalize(prefix);
+    let matches: Vec<&str> = names
+        .iter()
+        .copied()
+        .filter(|name| normalize(name).starts_with(&normalized))
+        .collect();
+    if !matches.is_empty() {
+        return matches;
+    }
+    names
+        .into_iter()
+        .filter(|name| match_kind(prefix, name).is_some())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 4] = ["亚索E+R触发QR", "亚索E+D触发QD", "Tab触发A+左键", "QWE连招"];
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(match_kind("qwe连招", "QWE连招"), Some(MatchKind::Exact));
+        assert_eq!(match_kind("tab", "Tab触发A+左键"), Some(MatchKind::Prefix));
+        assert_eq!(
+            match_kind("触发 qr", "亚索E+R触发QR"),
+            Some(MatchKind::Substring)
+        );
+        assert_eq!(
+            match_kind("亚索r", "亚索E+R触发QR"),
+            Some(MatchKind::Subsequence)
+        );
+        assert_eq!(match_kind("", "QWE连招"), None);
+
+        assert_eq!(lookup("亚索r", NAMES), Lookup::Found(0));
+        assert_eq!(lookup("亚索", NAMES), Lookup::Ambiguous(vec![0, 1]));
+        // 更精确的匹配优先于模糊匹配
+        assert_eq!(lookup("qwe", NAMES), Lookup::Found(3));
+        assert_eq!(lookup("亚索W", NAMES), Lookup::NotFound);
+    }
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(complete("亚", NAMES), ["亚索E+R触发QR", "亚索E+D触发QD"]);
+        assert_eq!(complete("qd", NAMES), ["亚索E+D触发QD"]);
+        assert_eq!(complete("", NAMES), NAMES);
+        assert!(complete("xyz", NAMES).is_empty());
+    }
+}
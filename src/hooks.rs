@@ -0,0 +1,323 @@
+// 配置的钩子：订阅事件总线，事件发生时在后台执行本机命令，不占用处理输入的线程
+use crate::config::{Config, HookConfig, HookEvent};
+use crate::events::{AppEvent, Envelope, EventBus};
+use crate::{debug, error, warn};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 等待命令结束时检查的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// 钩子和它响应的英雄：配置键名和显示名称都转换为小写，事件中的英雄可能是其中任意一个
+#[derive(Debug, Clone)]
+struct Hook {
+    config: HookConfig,
+    champions: HashSet<String>,
+}
+
+impl Hook {
+    fn new(hook: &HookConfig, config: &Config) -> Self {
+        let champions = hook
+            .champions
+            .iter()
+            .filter_map(|name| config.find_champion(name))
+            .flat_map(|(id, champion)| [id.to_lowercase(), champion.name.to_lowercase()])
+            .collect();
+        Hook {
+            config: hook.clone(),
+            champions,
+        }
+    }
+
+    fn matches(&self, event: &AppEvent) -> bool {
+        match (self.config.event, event) {
+            (HookEvent::ChampionSwitched, AppEvent::ChampionSwitched { champion }) => {
+                self.champions.is_empty()
+                    || champion
+                        .as_ref()
+                        .is_some_and(|name| self.champions.contains(&name.to_lowercase()))
+            }
+            (HookEvent::ComboTriggered, AppEvent::ComboTriggered { combo, .. }) => {
+                self.config.combos.is_empty() || self.config.combos.contains(combo)
+            }
+            (HookEvent::PauseToggled, AppEvent::PauseToggled { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+fn build(config: &Config) -> Vec<Hook> {
+    config
+        .hooks
+        .iter()
+        .map(|hook| Hook::new(hook, config))
+        .collect()
+}
+
+pub struct Hooks {
+    hooks: Arc<RwLock<Vec<Hook>>>,
+}
+
+impl Hooks {
+    // 订阅 events，每个匹配的钩子在单独的线程中执行；事件总线释放后停止
+    // 同一个钩子（按配置中的位置区分）上一次的执行还没有结束时跳过，事件再频繁也不会堆积线程和进程
+    pub fn start(config: &Config, events: &EventBus) -> Self {
+        let hooks = Arc::new(RwLock::new(build(config)));
+        let receiver = events.subscribe();
+        let shared = Arc::clone(&hooks);
+        let running = Arc::new(Mutex::new(HashSet::new()));
+        thread::spawn(move || {
+            for envelope in receiver {
+                let matched: Vec<(usize, HookConfig)> = shared
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, hook)| hook.matches(&envelope.event))
+                    .map(|(index, hook)| (index, hook.config.clone()))
+                    .collect();
+                if matched.is_empty() {
+                    continue;
+                }
+                let env = environment(&envelope);
+                for (index, hook) in matched {
+                    if !running.lock().unwrap().insert(index) {
+                        warn!("钩子 {} 上一次执行还没有结束，跳过本次事件", hook.command);
+                        continue;
+                    }
+                    let env = env.clone();
+                    let running = Arc::clone(&running);
+                    thread::spawn(move || {
+                        report(&hook, run(&hook, &env));
+                        running.lock().unwrap().remove(&index);
+                    });
+                }
+            }
+        });
+        Hooks { hooks }
+    }
+
+    // 重新加载配置后替换钩子，正在执行的命令不受影响
+    pub fn update(&self, config: &Config) {
+        *self.hooks.write().unwrap() = build(config);
+    }
+}
+
+// 事件的字段作为环境变量：HERO_RS_EVENT 是事件类型，其他字段为 HERO_RS_<字段名大写>，
+// 例如 HERO_RS_COMBO、HERO_RS_CHAMPION、HERO_RS_PAUSED；null 为空字符串
+pub fn environment(envelope: &Envelope) -> Vec<(String, String)> {
+    let Ok(Value::Object(fields)) = serde_json::to_value(envelope) else {
+        return Vec::new();
+    };
+    fields
+        .into_iter()
+        .map(|(name, value)| {
+            let name = match name.as_str() {
+                "type" => "EVENT".to_string(),
+                _ => name.to_uppercase(),
+            };
+            let value = match value {
+                Value::String(text) => text,
+                Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            (format!("HERO_RS_{}", name), value)
+        })
+        .collect()
+}
+
+// 命令在单独的进程组中执行，超时时可以连同它启动的进程一起终止
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command).process_group(0);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+// 只终止 sh 时它启动的命令会继续运行，因此终止整个进程组
+#[cfg(unix)]
+fn terminate(child: &mut Child) {
+    let killed = Command::new("kill")
+        .arg("-KILL")
+        .arg("--")
+        .arg(format!("-{}", child.id()))
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if !killed {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) {
+    let _ = child.kill();
+}
+
+// 执行钩子并等待结束，超时时终止命令并返回 None
+// 标准输出被丢弃，错误输出与 hero-rs 相同，便于排查钩子的问题
+pub fn run(hook: &HookConfig, env: &[(String, String)]) -> io::Result<Option<ExitStatus>> {
+    let mut child = shell(&hook.command)
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()?;
+    let deadline = Instant::now() + Duration::from_millis(hook.timeout_ms);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            terminate(&mut child);
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn report(hook: &HookConfig, result: io::Result<Option<ExitStatus>>) {
+    match result {
+        Ok(Some(status)) if status.success() => debug!("钩子执行完毕: {}", hook.command),
+        Ok(Some(status)) => warn!("钩子 {} 执行失败: {}", hook.command, status),
+        Ok(None) => warn!(
+            "钩子 {} 超过 {} 毫秒没有结束，已终止",
+            hook.command, hook.timeout_ms
+        ),
+        Err(e) => error!("无法执行钩子 {}: {}", hook.command, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(event: HookEvent, command: &str) -> HookConfig {
+        HookConfig {
+            event,
+            command: command.to_string(),
+            combos: Vec::new(),
+            champions: Vec::new(),
+            timeout_ms: 5000,
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let config = Config::builtin();
+        let mut switched = hook(HookEvent::ChampionSwitched, "true");
+        switched.champions.push("yasuo".to_string());
+        let switched = Hook::new(&switched, &config);
+        // 配置键名和显示名称都可以匹配
+        for name in ["Yasuo", "亚索"] {
+            let event = AppEvent::ChampionSwitched {
+                champion: Some(name.to_string()),
+            };
+            assert!(switched.matches(&event));
+        }
+        assert!(!switched.matches(&AppEvent::ChampionSwitched { champion: None }));
+
+        let mut triggered = hook(HookEvent::ComboTriggered, "true");
+        triggered.combos.push("QWE连招".to_string());
+        let triggered = Hook::new(&triggered, &config);
+        let event = |combo: &str| AppEvent::ComboTriggered {
+            combo: combo.to_string(),
+            key: None,
+        };
+        assert!(triggered.matches(&event("QWE连招")));
+        assert!(!triggered.matches(&event("R后普攻")));
+        assert!(!triggered.matches(&AppEvent::PauseToggled { paused: true }));
+    }
+
+    #[test]
+    fn test_environment() {
+        let envelope = Envelope {
+            timestamp_ms: 42,
+            event: AppEvent::ComboTriggered {
+                combo: "QWE连招".to_string(),
+                key: None,
+            },
+        };
+        let mut env = environment(&envelope);
+        env.sort();
+        let expected = [
+            ("HERO_RS_COMBO", "QWE连招"),
+            ("HERO_RS_EVENT", "combo_triggered"),
+            ("HERO_RS_KEY", ""),
+            ("HERO_RS_TIMESTAMP_MS", "42"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        assert_eq!(env, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        let env = [("HERO_RS_PAUSED".to_string(), "true".to_string())];
+        let status = run(
+            &hook(HookEvent::PauseToggled, "test \"$HERO_RS_PAUSED\" = true"),
+            &env,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(status.success());
+        let status = run(&hook(HookEvent::PauseToggled, "exit 3"), &env)
+            .unwrap()
+            .unwrap();
+        assert_eq!(status.code(), Some(3));
+
+        // 超时的命令被终止，包括它在后台启动的进程
+        let marker = std::env::temp_dir().join(format!("hero-rs-hook-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let mut slow = hook(
+            HookEvent::PauseToggled,
+            &format!("(sleep 0.3; touch '{}') & sleep 5", marker.display()),
+        );
+        slow.timeout_ms = 50;
+        let start = Instant::now();
+        assert!(run(&slow, &env).unwrap().is_none());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        thread::sleep(Duration::from_millis(500));
+        assert!(!marker.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_skip_while_running() {
+        let output = std::env::temp_dir().join(format!("hero-rs-hooks-{}", std::process::id()));
+        let _ = std::fs::remove_file(&output);
+        let mut config = Config::builtin();
+        config.hooks.push(hook(
+            HookEvent::PauseToggled,
+            &format!("echo $HERO_RS_PAUSED >> '{}'; sleep 1", output.display()),
+        ));
+        // 命令相同的另一个钩子单独计算，不会被第一个钩子跳过
+        let shared = config.hooks[0].clone();
+        config.hooks.push(shared);
+        let events = EventBus::new();
+        let _hooks = Hooks::start(&config, &events);
+        for paused in [true, false, true] {
+            events.publish(AppEvent::PauseToggled { paused });
+            thread::sleep(Duration::from_millis(100));
+        }
+        thread::sleep(Duration::from_millis(300));
+        // 每个钩子第一次执行还没有结束，之后的两次被跳过
+        let text = std::fs::read_to_string(&output).unwrap();
+        let _ = std::fs::remove_file(&output);
+        assert_eq!(text, "true\ntrue\n");
+    }
+}
@@ -0,0 +1,439 @@
+// 本机 HTTP 接口：只监听 127.0.0.1，提供 JSON 接口和编辑连招的网页
+use crate::events::Envelope;
+use serde_json::{json, Value};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Server};
+
+// 网页，所有操作都通过 /api 下的接口完成
+pub const INDEX_HTML: &str = include_str!("http/index.html");
+
+// 请求体的最大长度
+const MAX_BODY: u64 = 64 * 1024;
+
+// 事件流没有事件时发送注释的间隔，用于发现已经断开的客户端
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+// 解析后的请求：路径按 / 分段并解码，查询参数解码为名称和值，请求体为 JSON（没有请求体时为 null）
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: Vec<String>,
+    pub query: Vec<(String, String)>,
+    pub body: Value,
+}
+
+impl Request {
+    // 路径分段，便于用切片模式匹配路由
+    pub fn segments(&self) -> Vec<&str> {
+        self.path.iter().map(String::as_str).collect()
+    }
+
+    // 查询参数的值，有多个时取第一个
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub enum Body {
+    Json(Value),
+    Html(&'static str),
+    // Server-Sent Events 事件流，客户端断开前一直发送收到的事件；
+    // input 为 false 时不发送每个按键的事件（input_observed 和 key_blocked）
+    Events {
+        events: Receiver<Envelope>,
+        input: bool,
+    },
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: Body,
+}
+
+impl Response {
+    pub fn json(status: u16, value: Value) -> Self {
+        Response {
+            status,
+            body: Body::Json(value),
+        }
+    }
+
+    pub fn ok(value: Value) -> Self {
+        Self::json(200, value)
+    }
+
+    // 错误响应：{"error": "说明"}
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, json!({ "error": message.into() }))
+    }
+
+    pub fn html(page: &'static str) -> Self {
+        Response {
+            status: 200,
+            body: Body::Html(page),
+        }
+    }
+
+    pub fn events(events: Receiver<Envelope>, input: bool) -> Self {
+        Response {
+            status: 200,
+            body: Body::Events { events, input },
+        }
+    }
+}
+
+pub type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+// 在后台线程中处理请求，返回实际监听的地址（端口为 0 时由系统分配）
+// origins 是允许跨域读取事件流的网页来源（例如直播叠加层），其他来源的网页无法读取
+pub fn serve(port: u16, origins: Vec<String>, handler: Arc<Handler>) -> Result<SocketAddr, String> {
+    let server = Server::http((Ipv4Addr::LOCALHOST, port))
+        .map_err(|e| format!("无法监听 127.0.0.1:{}: {}", port, e))?;
+    let addr = server
+        .server_addr()
+        .to_ip()
+        .ok_or("HTTP 服务器没有监听 TCP 地址")?;
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let response = match parse_request(&mut request) {
+                Ok(parsed) => handler(&parsed),
+                Err(response) => response,
+            };
+            match response.body {
+                // 事件流一直占用连接，在单独的线程中发送
+                Body::Events { events, input } => {
+                    let origin = header(&request, "Origin")
+                        .filter(|origin| origins.iter().any(|allowed| allowed == origin))
+                        .map(String::from);
+                    thread::spawn(move || stream_events(request, events, input, origin));
+                }
+                body => {
+                    let _ = request.respond(to_tiny(response.status, body));
+                }
+            }
+        }
+    });
+    Ok(addr)
+}
+
+fn header<'a>(request: &'a tiny_http::Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+fn parse_request(request: &mut tiny_http::Request) -> Result<Request, Response> {
+    // 只接受以本机名称访问的请求，防止 DNS 重绑定让其他网站访问接口
+    let host = header(request, "Host").unwrap_or_default();
+    if !is_local_host(host) {
+        return Err(Response::error(403, format!("不接受主机名 {}", host)));
+    }
+
+    let method = request.method().as_str().to_string();
+    let url = request.url();
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let path = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(percent_decode)
+        .collect();
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect();
+
+    // 修改状态的请求即使没有请求体也必须声明为 JSON：其他网站的表单和 no-cors 请求
+    // 无法设置这个类型，跨域设置时浏览器会先发送预检请求，而这里不允许跨域
+    let content_type = header(request, "Content-Type").unwrap_or_default();
+    let is_json = content_type.starts_with("application/json");
+    if method != "GET" && !is_json {
+        return Err(Response::error(415, "请求必须是 application/json"));
+    }
+
+    let mut text = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY)
+        .read_to_string(&mut text)
+        .map_err(|e| Response::error(400, format!("无法读取请求体: {}", e)))?;
+    let body = if text.trim().is_empty() {
+        Value::Null
+    } else {
+        if !is_json {
+            return Err(Response::error(415, "请求体必须是 application/json"));
+        }
+        serde_json::from_str(&text)
+            .map_err(|e| Response::error(400, format!("请求体不是有效的 JSON: {}", e)))?
+    };
+    if method != "GET" && !body.is_null() && !body.is_object() {
+        return Err(Response::error(400, "请求体必须是 JSON 对象"));
+    }
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        body,
+    })
+}
+
+fn to_tiny(status: u16, body: Body) -> tiny_http::ResponseBox {
+    let (text, content_type) = match body {
+        Body::Json(value) => (value.to_string(), "application/json; charset=utf-8"),
+        Body::Html(page) => (page.to_string(), "text/html; charset=utf-8"),
+        Body::Events { .. } => unreachable!("事件流不经过 tiny_http 的响应"),
+    };
+    let header = Header::from_bytes("Content-Type", content_type).unwrap();
+    tiny_http::Response::from_string(text)
+        .with_status_code(status)
+        .with_header(header)
+        .boxed()
+}
+
+// 直接写出响应：tiny_http 的分块编码会缓存数据，事件无法及时送达
+// 事件中包含每个按键，只有 origin（已允许的来源）不为空时才允许该来源的网页跨域读取
+fn stream_events(
+    request: tiny_http::Request,
+    events: Receiver<Envelope>,
+    input: bool,
+    origin: Option<String>,
+) -> io::Result<()> {
+    let mut writer = request.into_writer();
+    let cors = origin
+        .map(|origin| {
+            format!(
+                "Access-Control-Allow-Origin: {}\r\nVary: Origin\r\n",
+                origin
+            )
+        })
+        .unwrap_or_default();
+    write!(
+        writer,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n{}Connection: close\r\n\r\n",
+        cors
+    )?;
+    writer.flush()?;
+    loop {
+        match events.recv_timeout(KEEPALIVE) {
+            Ok(envelope) if !input && envelope.event.is_input() => continue,
+            Ok(envelope) => {
+                let data = serde_json::to_string(&envelope)?;
+                write!(writer, "data: {}\n\n", data)?;
+            }
+            Err(RecvTimeoutError::Timeout) => write!(writer, ": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        writer.flush()?;
+    }
+}
+
+fn is_local_host(host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    matches!(name, "127.0.0.1" | "localhost")
+}
+
+// 解码 URL 中的 %XX 和 +，无效的编码保持原样
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{AppEvent, EventBus};
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("%E4%BA%9A%E7%B4%A2E+R"), "亚索E R");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+        assert!(is_local_host("127.0.0.1:8080"));
+        assert!(is_local_host("localhost"));
+        assert!(!is_local_host("evil.example:8080"));
+    }
+
+    // 发送一个请求，返回状态码和响应体
+    fn send(addr: SocketAddr, request: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).unwrap();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        let code = status.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (code, String::from_utf8(body).unwrap())
+    }
+
+    #[test]
+    fn test_serve() {
+        let handler =
+            |request: &Request| Response::ok(json!([request.method, request.path, request.body]));
+        let addr = serve(0, Vec::new(), Arc::new(handler)).unwrap();
+        assert!(addr.ip().is_loopback());
+
+        let (status, body) = send(
+            addr,
+            "GET /api/combos/%E4%BA%9A%E7%B4%A2 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body, r#"["GET",["api","combos","亚索"],null]"#);
+
+        let handler = |request: &Request| Response::ok(json!([request.query, request.param("b")]));
+        let query_addr = serve(0, Vec::new(), Arc::new(handler)).unwrap();
+        let (_, body) = send(
+            query_addr,
+            "GET /api?a&b=%E4%BA%9A+x&b=2 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert_eq!(body, r#"[[["a",""],["b","亚 x"],["b","2"]],"亚 x"]"#);
+
+        let json = r#"{"a":1}"#;
+        let request = format!(
+            "PUT /api HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            json.len(),
+            json
+        );
+        assert_eq!(
+            send(addr, &request),
+            (200, r#"["PUT",["api"],{"a":1}]"#.to_string())
+        );
+
+        // 其他主机名和非 JSON 的请求体被拒绝
+        let (status, _) = send(
+            addr,
+            "GET / HTTP/1.1\r\nHost: evil.example\r\nConnection: close\r\n\r\n",
+        );
+        assert_eq!(status, 403);
+        let request = "POST /api HTTP/1.1\r\nHost: localhost\r\nContent-Type: text/plain\r\nContent-Length: 3\r\nConnection: close\r\n\r\na=1";
+        assert_eq!(send(addr, request).0, 415);
+        // 没有请求体的修改请求同样必须声明为 JSON
+        let request = "POST /api/combos/a/run HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        assert_eq!(send(addr, request).0, 415);
+        let request = "POST /api/combos/a/run HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        assert_eq!(
+            send(addr, request),
+            (
+                200,
+                r#"["POST",["api","combos","a","run"],null]"#.to_string()
+            )
+        );
+    }
+
+    // 打开事件流，返回响应头和读取事件的连接
+    fn open_stream(addr: SocketAddr, request: &str) -> (String, BufReader<TcpStream>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut headers = String::new();
+        while !headers.ends_with("\r\n\r\n") {
+            reader.read_line(&mut headers).unwrap();
+        }
+        (headers, reader)
+    }
+
+    fn next_event(reader: &mut BufReader<TcpStream>) -> Value {
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(data) = line.strip_prefix("data: ") {
+                return serde_json::from_str(data).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let bus = Arc::new(EventBus::new());
+        let subscriber = Arc::clone(&bus);
+        let handler = move |request: &Request| {
+            Response::events(
+                subscriber.subscribe(),
+                request.param("input") == Some("true"),
+            )
+        };
+        let origins = vec!["http://overlay.example".to_string()];
+        let addr = serve(0, origins, Arc::new(handler)).unwrap();
+
+        // 默认不允许跨域读取，也不发送按键事件
+        let (headers, mut reader) = open_stream(
+            addr,
+            "GET /api/events HTTP/1.1\r\nHost: 127.0.0.1\r\nOrigin: http://evil.example\r\n\r\n",
+        );
+        assert!(headers.contains("text/event-stream"));
+        assert!(!headers.contains("Access-Control-Allow-Origin"));
+        bus.publish(AppEvent::KeyBlocked {
+            key: "R".to_string(),
+        });
+        // 发布的事件逐条送达，不等待缓冲区填满
+        bus.publish(AppEvent::PauseToggled { paused: true });
+        let data = next_event(&mut reader);
+        assert_eq!(data["type"], "pause_toggled");
+        assert_eq!(data["paused"], true);
+
+        // 允许的来源可以跨域读取，并且可以选择接收按键事件
+        let (headers, mut reader) = open_stream(
+            addr,
+            "GET /api/events?input=true HTTP/1.1\r\nHost: 127.0.0.1\r\nOrigin: http://overlay.example\r\n\r\n",
+        );
+        assert!(headers.contains("Access-Control-Allow-Origin: http://overlay.example\r\n"));
+        bus.publish(AppEvent::KeyBlocked {
+            key: "R".to_string(),
+        });
+        assert_eq!(next_event(&mut reader)["type"], "key_blocked");
+    }
+}
@@ -0,0 +1,229 @@
+use crate::config::TriggerType;
+use crate::pattern::{Automaton, Matcher};
+use rdev::Key;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+// 序列前缀树的节点
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: Vec<(Key, usize)>,
+    // 在此结束的序列：(连招下标, 超时时间)
+    terminals: Vec<(usize, u64)>,
+    // 此节点之下所有序列中最长的超时时间，用于提前丢弃过期的匹配
+    max_timeout_ms: u64,
+}
+
+impl TrieNode {
+    fn child(&self, key: Key) -> Option<usize> {
+        self.children
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|&(_, node)| node)
+    }
+}
+
+// 所有严格按键序列共享的前缀树，每次按键时推进一次
+#[derive(Debug)]
+struct SequenceTrie {
+    nodes: Vec<TrieNode>,
+    // 正在匹配中的节点：(节点, 第一个按键的时间)
+    active: Vec<(usize, Instant)>,
+    next: Vec<(usize, Instant)>,
+}
+
+impl SequenceTrie {
+    fn new() -> Self {
+        SequenceTrie {
+            nodes: vec![TrieNode::default()],
+            active: Vec::new(),
+            next: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, keys: &[Key], timeout_ms: u64, combo: usize) {
+        let mut node = 0;
+        for &key in keys {
+            self.nodes[node].max_timeout_ms = self.nodes[node].max_timeout_ms.max(timeout_ms);
+            node = match self.nodes[node].child(key) {
+                Some(child) => child,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children.push((key, child));
+                    child
+                }
+            };
+        }
+        self.nodes[node].max_timeout_ms = self.nodes[node].max_timeout_ms.max(timeout_ms);
+        self.nodes[node].terminals.push((combo, timeout_ms));
+    }
+
+    // 输入一个按下的按键，把完成匹配的连招下标写入 out
+    fn advance(&mut self, key: Key, time: Instant, out: &mut Vec<usize>) {
+        let SequenceTrie {
+            nodes,
+            active,
+            next,
+        } = self;
+
+        // 每个按键都可能是一个新序列的开头
+        active.push((0, time));
+
+        next.clear();
+        for &(node, start) in active.iter() {
+            let Some(child) = nodes[node].child(key) else {
+                continue;
+            };
+            let elapsed = time.duration_since(start).as_millis() as u64;
+            let child_node = &nodes[child];
+
+            for &(combo, timeout_ms) in &child_node.terminals {
+                if elapsed <= timeout_ms {
+                    out.push(combo);
+                }
+            }
+
+            if child_node.children.is_empty() || elapsed > child_node.max_timeout_ms {
+                continue;
+            }
+
+            // 同一节点只保留开始时间最晚的匹配
+            match next.iter_mut().find(|(n, _)| *n == child) {
+                Some(existing) => existing.1 = existing.1.max(start),
+                None => next.push((child, start)),
+            }
+        }
+
+        std::mem::swap(active, next);
+    }
+}
+
+// 触发索引：按最后一个按键查找候选连招，序列类触发共享前缀树
+// 在连招列表变化时重新构建，处理按键时不分配内存
+#[derive(Debug)]
+pub struct TriggerIndex {
+    // 单键和修饰键组合：触发键 -> 连招下标
+    by_key: HashMap<Key, Vec<usize>>,
+    // 严格按键序列
+    trie: SequenceTrie,
+    // 允许夹杂按键的序列和模式语法触发，各自维护自动机
+    matchers: Vec<(usize, Matcher)>,
+    // 复用的候选缓冲区
+    candidates: Vec<usize>,
+}
+
+impl TriggerIndex {
+    pub fn build<'a>(triggers: impl IntoIterator<Item = &'a TriggerType>) -> Self {
+        let mut by_key: HashMap<Key, Vec<usize>> = HashMap::new();
+        let mut trie = SequenceTrie::new();
+        let mut matchers = Vec::new();
+        let mut count = 0;
+
+        for (idx, trigger) in triggers.into_iter().enumerate() {
+            count += 1;
+            match trigger {
+                TriggerType::SingleKey { key } | TriggerType::KeyModifier { key, .. } => {
+                    by_key.entry(*key).or_default().push(idx);
+                }
+                TriggerType::KeySequence {
+                    keys,
+                    timeout_ms,
+                    max_interleaved,
+                    ignore_keys,
+                } => {
+                    if *max_interleaved == 0 && ignore_keys.is_empty() {
+                        trie.insert(keys, *timeout_ms, idx);
+                    } else {
+                        let automaton =
+                            Automaton::sequence(keys, *timeout_ms, *max_interleaved, ignore_keys);
+                        matchers.push((idx, Matcher::new(Arc::new(automaton))));
+                    }
+                }
+                TriggerType::Pattern { pattern } => {
+                    matchers.push((idx, Matcher::new(pattern.automaton())));
+                }
+                TriggerType::Manual => {}
+            }
+        }
+
+        TriggerIndex {
+            by_key,
+            trie,
+            matchers,
+            candidates: Vec::with_capacity(count),
+        }
+    }
+
+    // 输入一个按下的按键，返回按键模式匹配的连招下标（升序）
+    // 修饰键是否按下、连招是否启用等由调用方检查
+    pub fn dispatch(&mut self, key: Key, time: Instant) -> &[usize] {
+        self.candidates.clear();
+
+        if let Some(combos) = self.by_key.get(&key) {
+            self.candidates.extend_from_slice(combos);
+        }
+
+        self.trie.advance(key, time, &mut self.candidates);
+
+        for (idx, matcher) in &mut self.matchers {
+            if matcher.advance(key, time) {
+                self.candidates.push(*idx);
+            }
+        }
+
+        self.candidates.sort_unstable();
+        &self.candidates
+    }
+
+    // 最近一次 dispatch 得到的候选连招
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::Pattern;
+    use std::time::Duration;
+
+    #[test]
+    fn test_dispatch() {
+        let triggers = [
+            TriggerType::SingleKey { key: Key::KeyR },
+            TriggerType::KeySequence {
+                keys: vec![Key::KeyE, Key::KeyR],
+                timeout_ms: 150,
+                max_interleaved: 0,
+                ignore_keys: Vec::new(),
+            },
+            TriggerType::KeySequence {
+                keys: vec![Key::KeyQ, Key::KeyE, Key::KeyR],
+                timeout_ms: 300,
+                max_interleaved: 0,
+                ignore_keys: Vec::new(),
+            },
+            TriggerType::Pattern {
+                pattern: Pattern::parse("E . R").unwrap(),
+            },
+            TriggerType::Manual,
+        ];
+        let mut index = TriggerIndex::build(&triggers);
+        let now = Instant::now();
+        let at = |ms| now + Duration::from_millis(ms);
+
+        assert_eq!(index.dispatch(Key::KeyQ, at(0)), &[] as &[usize]);
+        assert_eq!(index.dispatch(Key::KeyE, at(100)), &[] as &[usize]);
+        assert_eq!(index.dispatch(Key::KeyR, at(200)), &[0, 1, 2]);
+
+        // E 和 R 间隔超过 150ms，只剩单键触发
+        index.dispatch(Key::KeyE, at(1000));
+        assert_eq!(index.dispatch(Key::KeyR, at(1200)), &[0]);
+
+        index.dispatch(Key::KeyE, at(2000));
+        index.dispatch(Key::KeyW, at(2010));
+        assert_eq!(index.dispatch(Key::KeyR, at(2020)), &[0, 3]);
+    }
+}
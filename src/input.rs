@@ -0,0 +1,288 @@
+use crate::clock::{Clock, SystemClock};
+use rdev::{Event, EventType, GrabError, ListenError, SimulateError};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(target_os = "linux")]
+pub mod evdev;
+
+// 事件回调：第二个参数是事件来自的设备（后端无法区分设备时为 None）
+// 返回 None 表示屏蔽该事件（只有能够屏蔽的输入源才会生效）
+pub type EventCallback =
+    Box<dyn Fn(Event, Option<&Arc<DeviceInfo>>) -> Option<Event> + Send + 'static>;
+
+// 输入设备的标识
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({:04x}:{:04x}, {})",
+            self.name,
+            self.vendor,
+            self.product,
+            self.path.display()
+        )
+    }
+}
+
+// 输入后端错误
+#[derive(Debug)]
+pub enum InputError {
+    Grab(GrabError),
+    Listen(ListenError),
+    Simulate(SimulateError),
+    // 直接读写输入设备时的错误：(说明, 原因)
+    Device(String, io::Error),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Grab(e) => write!(f, "无法拦截输入事件: {:?}", e),
+            InputError::Listen(e) => write!(f, "无法监听输入事件: {:?}", e),
+            InputError::Simulate(e) => write!(f, "无法模拟输入事件: {}", e),
+            InputError::Device(context, e) => write!(f, "{}: {}", context, e),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+// 输入源：阻塞运行，把每个输入事件交给回调
+pub trait InputSource: Send {
+    // 后端名称，用于显示
+    fn name(&self) -> &'static str;
+
+    // 是否能够屏蔽原始输入
+    fn can_block(&self) -> bool;
+
+    fn run(&mut self, callback: EventCallback) -> Result<(), InputError>;
+}
+
+// 输入输出端：注入模拟的键盘鼠标事件
+pub trait InputSink: Send + Sync {
+    fn send(&self, event: &EventType) -> Result<(), InputError>;
+
+    // 当前环境能否注入事件
+    fn can_inject(&self) -> bool {
+        true
+    }
+}
+
+// rdev grab 模式，可以屏蔽按键（需要管理员/root权限）
+pub struct RdevGrab;
+
+impl InputSource for RdevGrab {
+    fn name(&self) -> &'static str {
+        "rdev grab"
+    }
+
+    fn can_block(&self) -> bool {
+        true
+    }
+
+    fn run(&mut self, callback: EventCallback) -> Result<(), InputError> {
+        rdev::grab(move |event| callback(event, None)).map_err(InputError::Grab)
+    }
+}
+
+// rdev listen 模式，只能观察输入，无法屏蔽
+pub struct RdevListen;
+
+impl InputSource for RdevListen {
+    fn name(&self) -> &'static str {
+        "rdev listen"
+    }
+
+    fn can_block(&self) -> bool {
+        false
+    }
+
+    fn run(&mut self, callback: EventCallback) -> Result<(), InputError> {
+        rdev::listen(move |event| {
+            let _ = callback(event, None);
+        })
+        .map_err(InputError::Listen)
+    }
+}
+
+// 通过 rdev::simulate 注入事件
+pub struct RdevSink;
+
+impl InputSink for RdevSink {
+    fn send(&self, event: &EventType) -> Result<(), InputError> {
+        rdev::simulate(event).map_err(InputError::Simulate)
+    }
+
+    // Linux 下 rdev 通过 X11 注入事件，没有 X11 显示时无法注入
+    #[cfg(target_os = "linux")]
+    fn can_inject(&self) -> bool {
+        rdev::display_size().is_ok()
+    }
+}
+
+// 演习模式输出端：不注入事件，只把计划按下的按键及其时间输出到日志
+pub struct DryRunSink {
+    clock: Arc<dyn Clock>,
+    start: Instant,
+}
+
+impl DryRunSink {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let start = clock.now();
+        DryRunSink { clock, start }
+    }
+}
+
+impl InputSink for DryRunSink {
+    fn send(&self, event: &EventType) -> Result<(), InputError> {
+        let elapsed = self.clock.now().duration_since(self.start);
+        crate::info!(
+            "[dry-run {:>9.3}s] {}",
+            elapsed.as_secs_f64(),
+            describe(event)
+        );
+        Ok(())
+    }
+
+    fn can_inject(&self) -> bool {
+        false
+    }
+}
+
+// 事件的可读描述
+pub fn describe(event: &EventType) -> String {
+    match event {
+        EventType::KeyPress(key) => format!("按下 {:?}", key),
+        EventType::KeyRelease(key) => format!("释放 {:?}", key),
+        EventType::ButtonPress(button) => format!("按下鼠标 {:?}", button),
+        EventType::ButtonRelease(button) => format!("释放鼠标 {:?}", button),
+        EventType::MouseMove { x, y } => format!("移动鼠标到 ({}, {})", x, y),
+        EventType::Wheel { delta_x, delta_y } => format!("滚动滚轮 ({}, {})", delta_x, delta_y),
+    }
+}
+
+// 构造一个当前时间的输入事件
+pub fn event(event_type: EventType) -> Event {
+    event_at(event_type, SystemTime::now())
+}
+
+// 构造一个指定时间的输入事件
+pub fn event_at(event_type: EventType, time: SystemTime) -> Event {
+    Event {
+        time,
+        name: None,
+        event_type,
+    }
+}
+
+// 测试用输入源：依次回放预先准备的事件，并记录未被屏蔽的事件
+pub struct MockSource {
+    events: Vec<Event>,
+    device: Option<Arc<DeviceInfo>>,
+    passed: Arc<Mutex<Vec<Event>>>,
+}
+
+impl MockSource {
+    pub fn new(events: Vec<Event>) -> Self {
+        MockSource {
+            events,
+            device: None,
+            passed: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // 所有事件都来自指定的设备
+    pub fn with_device(mut self, device: DeviceInfo) -> Self {
+        self.device = Some(Arc::new(device));
+        self
+    }
+
+    // 未被屏蔽、传递给系统的事件
+    pub fn passed(&self) -> Vec<EventType> {
+        self.passed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| event.event_type)
+            .collect()
+    }
+}
+
+impl InputSource for MockSource {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn can_block(&self) -> bool {
+        true
+    }
+
+    fn run(&mut self, callback: EventCallback) -> Result<(), InputError> {
+        for event in self.events.drain(..) {
+            if let Some(event) = callback(event, self.device.as_ref()) {
+                self.passed.lock().unwrap().push(event);
+            }
+        }
+        Ok(())
+    }
+}
+
+// 测试用输出端：记录注入的事件及其时间
+#[derive(Clone)]
+pub struct MockSink {
+    events: Arc<Mutex<Vec<(Instant, EventType)>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MockSink {
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    // 使用指定的时钟记录事件时间
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        MockSink {
+            events: Arc::new(Mutex::new(Vec::new())),
+            clock,
+        }
+    }
+
+    pub fn events(&self) -> Vec<(Instant, EventType)> {
+        self.events.lock().unwrap().clone()
+    }
+
+    // 等待至少 count 个事件被注入（连招在后台线程中执行），超时后返回已有的事件
+    pub fn wait_for(&self, count: usize, timeout: Duration) -> Vec<EventType> {
+        let deadline = Instant::now() + timeout;
+        while self.events.lock().unwrap().len() < count && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        self.events().into_iter().map(|(_, event)| event).collect()
+    }
+}
+
+impl Default for MockSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputSink for MockSink {
+    fn send(&self, event: &EventType) -> Result<(), InputError> {
+        self.events.lock().unwrap().push((self.clock.now(), *event));
+        Ok(())
+    }
+}
@@ -0,0 +1,600 @@
+use super::{DeviceInfo, EventCallback, InputError, InputSink, InputSource};
+use evdev::{
+    uinput::VirtualDevice, AbsoluteAxisCode, AttributeSet, AttributeSetRef, Device, EventSummary,
+    EventType as EvdevEventType, InputEvent, KeyCode, MiscCode, RelativeAxisCode,
+    SynchronizationCode,
+};
+use rdev::{Button, Event, EventType, Key};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+// 虚拟设备名称，自动选择设备时跳过它，避免抓取自己发出的事件
+const VIRTUAL_DEVICE_NAME: &str = "hero-rs virtual input";
+
+// 设备文件所在目录
+const DEV_INPUT_DIR: &str = "/dev/input";
+
+macro_rules! convert_keys {
+    ($($ev_key:ident, $rdev_key:ident),* $(,)?) => {
+        fn evdev_key_to_rdev_key(code: KeyCode) -> Key {
+            match code {
+                $(
+                    KeyCode::$ev_key => Key::$rdev_key,
+                )*
+                _ => Key::Unknown(code.code() as u32),
+            }
+        }
+
+        fn rdev_key_to_evdev_key(key: Key) -> Option<KeyCode> {
+            match key {
+                $(
+                    Key::$rdev_key => Some(KeyCode::$ev_key),
+                )*
+                Key::Unknown(code) => u16::try_from(code).ok().map(KeyCode::new),
+            }
+        }
+
+        // 所有能够模拟的按键
+        fn mapped_keys() -> impl Iterator<Item = KeyCode> {
+            [$(KeyCode::$ev_key),*].into_iter()
+        }
+    };
+}
+
+#[rustfmt::skip]
+convert_keys!(
+    KEY_ESC, Escape,
+    KEY_1, Num1,
+    KEY_2, Num2,
+    KEY_3, Num3,
+    KEY_4, Num4,
+    KEY_5, Num5,
+    KEY_6, Num6,
+    KEY_7, Num7,
+    KEY_8, Num8,
+    KEY_9, Num9,
+    KEY_0, Num0,
+    KEY_MINUS, Minus,
+    KEY_EQUAL, Equal,
+    KEY_BACKSPACE, Backspace,
+    KEY_TAB, Tab,
+    KEY_Q, KeyQ,
+    KEY_W, KeyW,
+    KEY_E, KeyE,
+    KEY_R, KeyR,
+    KEY_T, KeyT,
+    KEY_Y, KeyY,
+    KEY_U, KeyU,
+    KEY_I, KeyI,
+    KEY_O, KeyO,
+    KEY_P, KeyP,
+    KEY_LEFTBRACE, LeftBracket,
+    KEY_RIGHTBRACE, RightBracket,
+    KEY_ENTER, Return,
+    KEY_LEFTCTRL, ControlLeft,
+    KEY_A, KeyA,
+    KEY_S, KeyS,
+    KEY_D, KeyD,
+    KEY_F, KeyF,
+    KEY_G, KeyG,
+    KEY_H, KeyH,
+    KEY_J, KeyJ,
+    KEY_K, KeyK,
+    KEY_L, KeyL,
+    KEY_SEMICOLON, SemiColon,
+    KEY_APOSTROPHE, Quote,
+    KEY_GRAVE, BackQuote,
+    KEY_LEFTSHIFT, ShiftLeft,
+    KEY_BACKSLASH, BackSlash,
+    KEY_Z, KeyZ,
+    KEY_X, KeyX,
+    KEY_C, KeyC,
+    KEY_V, KeyV,
+    KEY_B, KeyB,
+    KEY_N, KeyN,
+    KEY_M, KeyM,
+    KEY_COMMA, Comma,
+    KEY_DOT, Dot,
+    KEY_SLASH, Slash,
+    KEY_RIGHTSHIFT, ShiftRight,
+    KEY_KPASTERISK, KpMultiply,
+    KEY_LEFTALT, Alt,
+    KEY_SPACE, Space,
+    KEY_CAPSLOCK, CapsLock,
+    KEY_F1, F1,
+    KEY_F2, F2,
+    KEY_F3, F3,
+    KEY_F4, F4,
+    KEY_F5, F5,
+    KEY_F6, F6,
+    KEY_F7, F7,
+    KEY_F8, F8,
+    KEY_F9, F9,
+    KEY_F10, F10,
+    KEY_NUMLOCK, NumLock,
+    KEY_SCROLLLOCK, ScrollLock,
+    KEY_KP7, Kp7,
+    KEY_KP8, Kp8,
+    KEY_KP9, Kp9,
+    KEY_KPMINUS, KpMinus,
+    KEY_KP4, Kp4,
+    KEY_KP5, Kp5,
+    KEY_KP6, Kp6,
+    KEY_KPPLUS, KpPlus,
+    KEY_KP1, Kp1,
+    KEY_KP2, Kp2,
+    KEY_KP3, Kp3,
+    KEY_KP0, Kp0,
+    KEY_KPDOT, KpDelete,
+    KEY_102ND, IntlBackslash,
+    KEY_F11, F11,
+    KEY_F12, F12,
+    KEY_KPENTER, KpReturn,
+    KEY_RIGHTCTRL, ControlRight,
+    KEY_KPSLASH, KpDivide,
+    KEY_RIGHTALT, AltGr,
+    KEY_HOME, Home,
+    KEY_UP, UpArrow,
+    KEY_PAGEUP, PageUp,
+    KEY_LEFT, LeftArrow,
+    KEY_RIGHT, RightArrow,
+    KEY_END, End,
+    KEY_DOWN, DownArrow,
+    KEY_PAGEDOWN, PageDown,
+    KEY_INSERT, Insert,
+    KEY_DELETE, Delete,
+    KEY_PAUSE, Pause,
+    KEY_LEFTMETA, MetaLeft,
+    KEY_RIGHTMETA, MetaRight,
+    KEY_SYSRQ, PrintScreen,
+    KEY_FN, Function,
+);
+
+// 鼠标按钮：侧键按 X11 的习惯编号为 8 和 9
+const BUTTONS: [(KeyCode, Button); 5] = [
+    (KeyCode::BTN_LEFT, Button::Left),
+    (KeyCode::BTN_RIGHT, Button::Right),
+    (KeyCode::BTN_MIDDLE, Button::Middle),
+    (KeyCode::BTN_SIDE, Button::Unknown(8)),
+    (KeyCode::BTN_EXTRA, Button::Unknown(9)),
+];
+
+fn evdev_key_to_rdev_button(code: KeyCode) -> Option<Button> {
+    BUTTONS
+        .iter()
+        .find(|(ev_key, _)| *ev_key == code)
+        .map(|&(_, button)| button)
+}
+
+fn rdev_button_to_evdev_key(button: Button) -> Option<KeyCode> {
+    BUTTONS
+        .iter()
+        .find(|(_, rdev_button)| *rdev_button == button)
+        .map(|&(ev_key, _)| ev_key)
+}
+
+// 把 evdev 的按键事件转换为 rdev 事件，其他事件（移动、滚轮、同步等）直接放行
+fn convert_event(event: &InputEvent) -> Option<EventType> {
+    let EventSummary::Key(_, code, value) = event.destructure() else {
+        return None;
+    };
+    // value: 0 释放，1 按下，2 按住自动重复（与 X11 一样作为按下处理）
+    let pressed = value != 0;
+    Some(match (evdev_key_to_rdev_button(code), pressed) {
+        (Some(button), true) => EventType::ButtonPress(button),
+        (Some(button), false) => EventType::ButtonRelease(button),
+        (None, true) => EventType::KeyPress(evdev_key_to_rdev_key(code)),
+        (None, false) => EventType::KeyRelease(evdev_key_to_rdev_key(code)),
+    })
+}
+
+// 把要模拟的 rdev 事件转换为 evdev 事件
+fn simulated_event(event: &EventType) -> Option<InputEvent> {
+    let key_event = |code: KeyCode, value| InputEvent::new(EvdevEventType::KEY.0, code.0, value);
+    let rel_event =
+        |code: RelativeAxisCode, value| InputEvent::new(EvdevEventType::RELATIVE.0, code.0, value);
+    match *event {
+        EventType::KeyPress(key) => rdev_key_to_evdev_key(key).map(|code| key_event(code, 1)),
+        EventType::KeyRelease(key) => rdev_key_to_evdev_key(key).map(|code| key_event(code, 0)),
+        EventType::ButtonPress(button) => {
+            rdev_button_to_evdev_key(button).map(|code| key_event(code, 1))
+        }
+        EventType::ButtonRelease(button) => {
+            rdev_button_to_evdev_key(button).map(|code| key_event(code, 0))
+        }
+        EventType::Wheel {
+            delta_x: 0,
+            delta_y,
+        } => Some(rel_event(RelativeAxisCode::REL_WHEEL, delta_y as i32)),
+        EventType::Wheel { delta_x, .. } => {
+            Some(rel_event(RelativeAxisCode::REL_HWHEEL, delta_x as i32))
+        }
+        // 虚拟设备只有相对坐标轴，无法移动到绝对位置
+        EventType::MouseMove { .. } => None,
+    }
+}
+
+// 是否是键盘或鼠标（支持字母键或鼠标左键）
+fn is_keyboard_or_mouse(device: &Device) -> bool {
+    is_selectable(device.supported_keys(), device.supported_absolute_axes())
+}
+
+// 触摸板、绘图板和触摸屏同样支持鼠标左键，但使用绝对坐标定位，
+// 虚拟设备只有相对坐标轴，抓取后它们将无法使用，因此自动选择时跳过
+fn is_selectable(
+    keys: Option<&AttributeSetRef<KeyCode>>,
+    absolute_axes: Option<&AttributeSetRef<AbsoluteAxisCode>>,
+) -> bool {
+    !is_absolute_pointer(absolute_axes)
+        && keys
+            .is_some_and(|keys| keys.contains(KeyCode::KEY_A) || keys.contains(KeyCode::BTN_LEFT))
+}
+
+fn is_absolute_pointer(absolute_axes: Option<&AttributeSetRef<AbsoluteAxisCode>>) -> bool {
+    absolute_axes.is_some_and(|axes| {
+        axes.contains(AbsoluteAxisCode::ABS_X) || axes.contains(AbsoluteAxisCode::ABS_MT_POSITION_X)
+    })
+}
+
+fn device_info(path: &Path, device: &Device) -> DeviceInfo {
+    let id = device.input_id();
+    DeviceInfo {
+        path: path.to_path_buf(),
+        name: device.name().unwrap_or("未知设备").to_string(),
+        vendor: id.vendor(),
+        product: id.product(),
+    }
+}
+
+// 列出当前连接的键盘和鼠标（不包括本程序的虚拟设备），按路径排序
+pub fn list_devices() -> Vec<DeviceInfo> {
+    let mut devices: Vec<DeviceInfo> = evdev::enumerate()
+        .filter(|(_, device)| device.name() != Some(VIRTUAL_DEVICE_NAME))
+        .filter(|(_, device)| is_keyboard_or_mouse(device))
+        .map(|(path, device)| device_info(&path, &device))
+        .collect();
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+    devices
+}
+
+// evdev/uinput 后端：独占抓取 /dev/input/event* 设备，放行的事件和模拟的事件
+// 都通过同一个 uinput 虚拟设备重新发出。不依赖 X11，可以在 Wayland 和控制台下使用
+// （需要读取 /dev/input 和写入 /dev/uinput 的权限）
+pub struct EvdevGrab {
+    devices: Vec<(Arc<DeviceInfo>, Device)>,
+    output: Arc<Mutex<VirtualDevice>>,
+    // 是否独占设备；不独占时只监听，原始事件照常送达系统，无法屏蔽
+    exclusive: bool,
+}
+
+impl EvdevGrab {
+    // 打开指定的设备，为空时自动选择所有键盘和鼠标
+    pub fn open(paths: &[PathBuf]) -> Result<Self, InputError> {
+        let devices: Vec<(Arc<DeviceInfo>, Device)> = if paths.is_empty() {
+            let mut devices: Vec<_> = evdev::enumerate()
+                .filter(|(_, device)| device.name() != Some(VIRTUAL_DEVICE_NAME))
+                .filter(|(_, device)| is_keyboard_or_mouse(device))
+                .map(|(path, device)| (Arc::new(device_info(&path, &device)), device))
+                .collect();
+            devices.sort_by(|(a, _), (b, _)| a.path.cmp(&b.path));
+            devices
+        } else {
+            paths
+                .iter()
+                .map(|path| {
+                    let device = Device::open(path)
+                        .map_err(|e| device_error(format!("无法打开设备 {}", path.display()), e))?;
+                    let info = device_info(path, &device);
+                    if is_absolute_pointer(device.supported_absolute_axes()) {
+                        crate::warn!("设备 {} 使用绝对坐标定位，抓取后指针将无法移动", info);
+                    }
+                    Ok((Arc::new(info), device))
+                })
+                .collect::<Result<_, _>>()?
+        };
+        if devices.is_empty() {
+            return Err(device_error(
+                format!("在 {} 下没有找到可用的键盘或鼠标", DEV_INPUT_DIR),
+                io::ErrorKind::NotFound.into(),
+            ));
+        }
+
+        // 虚拟设备需要支持被抓取设备的所有按键和坐标轴，以及所有可以模拟的按键
+        let mut keys: AttributeSet<KeyCode> = mapped_keys().collect();
+        let mut axes = AttributeSet::<RelativeAxisCode>::new();
+        axes.insert(RelativeAxisCode::REL_WHEEL);
+        axes.insert(RelativeAxisCode::REL_HWHEEL);
+        for (code, _) in BUTTONS {
+            keys.insert(code);
+        }
+        for (_, device) in &devices {
+            for code in device
+                .supported_keys()
+                .into_iter()
+                .flat_map(|set| set.iter())
+            {
+                keys.insert(code);
+            }
+            for axis in device
+                .supported_relative_axes()
+                .into_iter()
+                .flat_map(|set| set.iter())
+            {
+                axes.insert(axis);
+            }
+        }
+        let mut misc = AttributeSet::<MiscCode>::new();
+        misc.insert(MiscCode::MSC_SCAN);
+
+        let output = VirtualDevice::builder()
+            .and_then(|builder| builder.name(VIRTUAL_DEVICE_NAME).with_keys(&keys))
+            .and_then(|builder| builder.with_relative_axes(&axes))
+            .and_then(|builder| builder.with_msc(&misc))
+            .and_then(|builder| builder.build())
+            .map_err(|e| device_error("无法创建 uinput 虚拟设备".to_string(), e))?;
+
+        Ok(EvdevGrab {
+            devices,
+            output: Arc::new(Mutex::new(output)),
+            exclusive: true,
+        })
+    }
+
+    // 以只监听的方式重新打开同样的设备，与当前对象共用虚拟设备
+    pub fn listener(&self) -> Result<EvdevGrab, InputError> {
+        let devices = self
+            .devices
+            .iter()
+            .map(|(info, _)| {
+                Device::open(&info.path)
+                    .map(|device| (Arc::clone(info), device))
+                    .map_err(|e| device_error(format!("无法打开设备 {}", info.path.display()), e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(EvdevGrab {
+            devices,
+            output: Arc::clone(&self.output),
+            exclusive: false,
+        })
+    }
+
+    // 被抓取的设备
+    pub fn devices(&self) -> impl Iterator<Item = &DeviceInfo> {
+        self.devices.iter().map(|(info, _)| info.as_ref())
+    }
+
+    // 与输入源共用虚拟设备的输出端
+    pub fn sink(&self) -> EvdevSink {
+        EvdevSink {
+            output: Arc::clone(&self.output),
+        }
+    }
+}
+
+impl InputSource for EvdevGrab {
+    fn name(&self) -> &'static str {
+        if self.exclusive {
+            "evdev grab"
+        } else {
+            "evdev listen"
+        }
+    }
+
+    fn can_block(&self) -> bool {
+        self.exclusive
+    }
+
+    fn run(&mut self, callback: EventCallback) -> Result<(), InputError> {
+        if self.exclusive {
+            for i in 0..self.devices.len() {
+                let (info, device) = &mut self.devices[i];
+                if let Err(e) = device.grab() {
+                    let error = device_error(format!("无法独占设备 {}", info.path.display()), e);
+                    // 释放已经独占的设备，以便调用方改用监听模式
+                    for (_, device) in &mut self.devices[..i] {
+                        let _ = device.ungrab();
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        // 每个设备一个读取线程，按批（以 SYN_REPORT 结尾）交给当前线程处理
+        let (tx, rx) = mpsc::channel::<(Arc<DeviceInfo>, Vec<InputEvent>)>();
+        for (info, mut device) in self.devices.drain(..) {
+            let tx = tx.clone();
+            thread::spawn(move || loop {
+                let batch = match device.fetch_events() {
+                    Ok(events) => events.collect(),
+                    Err(e) => {
+                        crate::warn!("设备 {} 读取失败，停止抓取: {}", info, e);
+                        break;
+                    }
+                };
+                if tx.send((Arc::clone(&info), batch)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        // 所有设备都断开后返回
+        for (info, batch) in rx {
+            let mut passed = Vec::with_capacity(batch.len());
+            for event in batch {
+                if event.event_type() == EvdevEventType::SYNCHRONIZATION
+                    && event.code() == SynchronizationCode::SYN_REPORT.0
+                {
+                    continue;
+                }
+                let keep = match convert_event(&event) {
+                    Some(event_type) => {
+                        let event = Event {
+                            time: event.timestamp(),
+                            name: None,
+                            event_type,
+                        };
+                        callback(event, Some(&info)).is_some()
+                    }
+                    None => true,
+                };
+                if keep {
+                    passed.push(event);
+                }
+            }
+            // 不独占时原始事件已经送达系统，不需要重新发出
+            if self.exclusive && !passed.is_empty() {
+                self.output
+                    .lock()
+                    .unwrap()
+                    .emit(&passed)
+                    .map_err(|e| device_error("无法写入虚拟设备".to_string(), e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// 通过 uinput 虚拟设备注入事件
+pub struct EvdevSink {
+    output: Arc<Mutex<VirtualDevice>>,
+}
+
+impl InputSink for EvdevSink {
+    fn send(&self, event: &EventType) -> Result<(), InputError> {
+        let Some(event) = simulated_event(event) else {
+            return Err(device_error(
+                format!("evdev 后端无法模拟事件 {:?}", event),
+                io::ErrorKind::Unsupported.into(),
+            ));
+        };
+        self.output
+            .lock()
+            .unwrap()
+            .emit(&[event])
+            .map_err(|e| device_error("无法写入虚拟设备".to_string(), e))
+    }
+}
+
+// 附带说明的设备错误
+fn device_error(context: String, source: io::Error) -> InputError {
+    InputError::Device(context, source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_key_mapping_roundtrip() {
+        for code in mapped_keys() {
+            let key = evdev_key_to_rdev_key(code);
+            assert!(!matches!(key, Key::Unknown(_)), "{:?}", code);
+            assert_eq!(rdev_key_to_evdev_key(key), Some(code));
+        }
+        assert_eq!(evdev_key_to_rdev_key(KeyCode::KEY_F13), Key::Unknown(183));
+        assert_eq!(
+            rdev_key_to_evdev_key(Key::Unknown(183)),
+            Some(KeyCode::KEY_F13)
+        );
+
+        let press = InputEvent::new(EvdevEventType::KEY.0, KeyCode::BTN_SIDE.0, 1);
+        assert_eq!(
+            convert_event(&press),
+            Some(EventType::ButtonPress(Button::Unknown(8)))
+        );
+        let repeat = InputEvent::new(EvdevEventType::KEY.0, KeyCode::KEY_Q.0, 2);
+        assert_eq!(convert_event(&repeat), Some(EventType::KeyPress(Key::KeyQ)));
+        let motion = InputEvent::new(EvdevEventType::RELATIVE.0, RelativeAxisCode::REL_X.0, 3);
+        assert_eq!(convert_event(&motion), None);
+    }
+
+    #[test]
+    fn test_is_selectable() {
+        let keyboard: AttributeSet<KeyCode> =
+            [KeyCode::KEY_A, KeyCode::KEY_Q].into_iter().collect();
+        let mouse: AttributeSet<KeyCode> = [KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT]
+            .into_iter()
+            .collect();
+        let power: AttributeSet<KeyCode> = [KeyCode::KEY_POWER].into_iter().collect();
+        let touchpad: AttributeSet<AbsoluteAxisCode> = [
+            AbsoluteAxisCode::ABS_X,
+            AbsoluteAxisCode::ABS_Y,
+            AbsoluteAxisCode::ABS_MT_POSITION_X,
+        ]
+        .into_iter()
+        .collect();
+        // 只有音量等绝对坐标轴的设备不受影响
+        let volume: AttributeSet<AbsoluteAxisCode> =
+            [AbsoluteAxisCode::ABS_VOLUME].into_iter().collect();
+
+        assert!(is_selectable(Some(&keyboard), None));
+        assert!(is_selectable(Some(&mouse), None));
+        assert!(is_selectable(Some(&keyboard), Some(&volume)));
+        assert!(!is_selectable(Some(&power), None));
+        assert!(!is_selectable(None, None));
+        // 触摸板和绘图板虽然支持鼠标左键，但不会被自动抓取
+        assert!(!is_selectable(Some(&mouse), Some(&touchpad)));
+    }
+
+    // 需要 /dev/uinput 和 /dev/input 的读写权限，默认不运行，
+    // 使用 cargo test -- --ignored 运行（CI 的 Linux 任务中加载 uinput 模块后运行）
+    #[test]
+    #[ignore]
+    fn test_uinput_grab_and_passthrough() {
+        let keys: AttributeSet<KeyCode> = [KeyCode::KEY_E, KeyCode::KEY_R].into_iter().collect();
+        let mut fake = VirtualDevice::builder()
+            .and_then(|builder| builder.name("hero-rs test keyboard").with_keys(&keys))
+            .and_then(|builder| builder.build())
+            .expect("无法创建 uinput 虚拟设备");
+        let fake_path = fake
+            .enumerate_dev_nodes_blocking()
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        let mut grab = EvdevGrab::open(&[fake_path]).unwrap();
+        let mut output = grab
+            .output
+            .lock()
+            .unwrap()
+            .enumerate_dev_nodes_blocking()
+            .unwrap();
+        let mut output = Device::open(output.next().unwrap().unwrap()).unwrap();
+        thread::spawn(move || {
+            // 屏蔽 R，放行其他按键
+            let _ = grab.run(Box::new(|event, _| match event.event_type {
+                EventType::KeyPress(Key::KeyR) | EventType::KeyRelease(Key::KeyR) => None,
+                _ => Some(event),
+            }));
+        });
+        thread::sleep(Duration::from_millis(200));
+
+        let key = |code: KeyCode, value| InputEvent::new(EvdevEventType::KEY.0, code.0, value);
+        fake.emit(&[key(KeyCode::KEY_R, 1)]).unwrap();
+        fake.emit(&[key(KeyCode::KEY_R, 0)]).unwrap();
+        fake.emit(&[key(KeyCode::KEY_E, 1)]).unwrap();
+        fake.emit(&[key(KeyCode::KEY_E, 0)]).unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            received.extend(
+                output
+                    .fetch_events()
+                    .unwrap()
+                    .filter_map(|e| convert_event(&e)),
+            );
+        }
+        assert_eq!(
+            received,
+            [
+                EventType::KeyPress(Key::KeyE),
+                EventType::KeyRelease(Key::KeyE)
+            ]
+        );
+    }
+}
@@ -0,0 +1,368 @@
+// 守护进程的控制接口：Unix 套接字上的 JSON-RPC 2.0，每行一个请求或响应
+use crate::events::{Envelope, EventBus};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+// JSON-RPC 规定的错误码
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+// 请求有效但无法执行，例如找不到连招
+pub const COMMAND_FAILED: i64 = -32000;
+
+// 订阅事件的方法：返回 true 之后，连接只用于推送 event 通知，直到任意一方断开
+pub const SUBSCRIBE: &str = "subscribe";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+impl Request {
+    pub fn new(id: u64, method: &str, params: Value) -> Self {
+        Request {
+            jsonrpc: "2.0".to_string(),
+            id: id.into(),
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}（错误码 {}）", self.message, self.code)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(value) => (Some(value), None),
+            Err(error) => (None, Some(error)),
+        };
+        Response {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+// 服务端推送的通知（没有 id），params 为发布的事件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Notification<'a> {
+    jsonrpc: &'static str,
+    method: &'static str,
+    params: &'a Envelope,
+}
+
+// 处理一个方法调用：方法名和参数，返回结果或错误
+pub type Handler = dyn Fn(&str, Value) -> Result<Value, RpcError> + Send + Sync;
+
+// 处理一行请求
+pub fn handle_line(line: &str, handler: &Handler) -> Response {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, format!("无法解析请求: {}", e));
+            return Response::new(Value::Null, Err(error));
+        }
+    };
+    if request.jsonrpc != "2.0" {
+        let error = RpcError::new(INVALID_REQUEST, "只支持 JSON-RPC 2.0");
+        return Response::new(request.id, Err(error));
+    }
+    Response::new(request.id, handler(&request.method, request.params))
+}
+
+// 默认的套接字路径：$XDG_RUNTIME_DIR/hero-rs.sock，没有运行时目录时放在状态目录
+pub fn default_socket_path() -> Option<PathBuf> {
+    let dir = dirs::runtime_dir().or_else(crate::profile::state_dir)?;
+    Some(dir.join("hero-rs.sock"))
+}
+
+// pid 文件与套接字放在同一目录
+pub fn pid_path(socket_path: &Path) -> PathBuf {
+    socket_path.with_extension("pid")
+}
+
+// 单实例锁：运行期间持有 pid 文件的独占锁并监听套接字，释放时删除这两个文件
+pub struct Instance {
+    _pid_file: File,
+    pid_path: PathBuf,
+    socket_path: PathBuf,
+}
+
+impl Instance {
+    pub fn acquire(socket_path: &Path) -> Result<(Instance, UnixListener), String> {
+        if let Some(dir) = socket_path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("无法创建目录 {}: {}", dir.display(), e))?;
+        }
+        let pid_path = pid_path(socket_path);
+        let mut pid_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&pid_path)
+            .map_err(|e| format!("无法打开 pid 文件 {}: {}", pid_path.display(), e))?;
+        match pid_file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let pid = fs::read_to_string(&pid_path).unwrap_or_default();
+                return Err(format!(
+                    "hero-rs 已经在运行（pid {}，控制套接字 {}）",
+                    pid.trim(),
+                    socket_path.display()
+                ));
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(format!("无法锁定 pid 文件 {}: {}", pid_path.display(), e));
+            }
+        }
+        pid_file
+            .set_len(0)
+            .and_then(|_| writeln!(pid_file, "{}", std::process::id()))
+            .map_err(|e| format!("无法写入 pid 文件 {}: {}", pid_path.display(), e))?;
+
+        // 拿到锁时残留的套接字来自异常退出的进程
+        let _ = fs::remove_file(socket_path);
+        let listener = UnixListener::bind(socket_path)
+            .map_err(|e| format!("无法监听套接字 {}: {}", socket_path.display(), e))?;
+        // 只允许当前用户连接
+        let _ = fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600));
+
+        let instance = Instance {
+            _pid_file: pid_file,
+            pid_path,
+            socket_path: socket_path.to_path_buf(),
+        };
+        Ok((instance, listener))
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket_path);
+        let _ = fs::remove_file(&self.pid_path);
+    }
+}
+
+// 在后台线程中接受连接，每个连接一个线程，按行处理请求；subscribe 请求订阅 events 中的事件
+pub fn serve(listener: UnixListener, handler: Arc<Handler>, events: Arc<EventBus>) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let handler = Arc::clone(&handler);
+            let events = Arc::clone(&events);
+            thread::spawn(move || {
+                let _ = handle_connection(stream, handler.as_ref(), &events);
+            });
+        }
+    });
+}
+
+fn handle_connection(stream: UnixStream, handler: &Handler, events: &EventBus) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let subscribe = serde_json::from_str::<Request>(&line)
+            .ok()
+            .filter(|request| request.jsonrpc == "2.0" && request.method == SUBSCRIBE);
+        if let Some(request) = subscribe {
+            // 先订阅再响应，客户端收到响应之后发布的事件都会送达
+            let receiver = events.subscribe();
+            write_line(&mut writer, &Response::new(request.id, Ok(true.into())))?;
+            // 断开的客户端在下一次推送时发现
+            for envelope in receiver {
+                let notification = Notification {
+                    jsonrpc: "2.0",
+                    method: "event",
+                    params: &envelope,
+                };
+                write_line(&mut writer, &notification)?;
+            }
+            return Ok(());
+        }
+        write_line(&mut writer, &handle_line(&line, handler))?;
+    }
+    Ok(())
+}
+
+fn write_line(writer: &mut UnixStream, message: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, message)?;
+    writeln!(writer)
+}
+
+// 客户端：调用一个方法并等待结果
+pub fn call(socket_path: &Path, method: &str, params: Value) -> Result<Value, String> {
+    let mut reader = send(socket_path, method, params)?;
+    read_result(&mut reader)
+}
+
+// 客户端：订阅事件，对每个事件调用 on_event，直到守护进程退出
+pub fn subscribe(socket_path: &Path, mut on_event: impl FnMut(Value)) -> Result<(), String> {
+    let mut reader = send(socket_path, SUBSCRIBE, Value::Null)?;
+    read_result(&mut reader)?;
+    for line in reader.lines() {
+        let line = line.map_err(|e| format!("读取事件失败: {}", e))?;
+        let mut notification: Value =
+            serde_json::from_str(&line).map_err(|e| format!("无法解析事件: {}", e))?;
+        on_event(notification["params"].take());
+    }
+    Ok(())
+}
+
+// 连接套接字并发送一个请求
+fn send(socket_path: &Path, method: &str, params: Value) -> Result<BufReader<UnixStream>, String> {
+    let mut stream = UnixStream::connect(socket_path).map_err(|e| {
+        format!(
+            "无法连接控制套接字 {}（hero-rs run --daemon 是否在运行？）: {}",
+            socket_path.display(),
+            e
+        )
+    })?;
+    let request = Request::new(1, method, params);
+    let mut line = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .map_err(|e| format!("发送请求失败: {}", e))?;
+    Ok(BufReader::new(stream))
+}
+
+fn read_result(reader: &mut BufReader<UnixStream>) -> Result<Value, String> {
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .map_err(|e| format!("读取响应失败: {}", e))?;
+    let response: Response =
+        serde_json::from_str(&line).map_err(|e| format!("无法解析响应: {}", e))?;
+    match response.error {
+        Some(error) => Err(error.to_string()),
+        // 结果为 null 时反序列化为 None
+        None => Ok(response.result.unwrap_or(Value::Null)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::AppEvent;
+    use serde_json::json;
+    use std::env;
+    use std::process;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn echo(method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "echo" => Ok(params),
+            "fail" => Err(RpcError::new(COMMAND_FAILED, "失败")),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "未知方法")),
+        }
+    }
+
+    #[test]
+    fn test_handle_line() {
+        let response = handle_line(
+            r#"{"jsonrpc":"2.0","id":7,"method":"echo","params":{"a":1}}"#,
+            &echo,
+        );
+        assert_eq!(response.id, json!(7));
+        assert_eq!(response.result, Some(json!({"a": 1})));
+
+        let response = handle_line(r#"{"jsonrpc":"2.0","id":"x","method":"nope"}"#, &echo);
+        assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+        let response = handle_line(r#"{"jsonrpc":"1.0","id":1,"method":"echo"}"#, &echo);
+        assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+        let response = handle_line("not json", &echo);
+        assert_eq!(response.id, Value::Null);
+        assert_eq!(response.error.unwrap().code, PARSE_ERROR);
+    }
+
+    #[test]
+    fn test_instance_and_call() {
+        let dir = env::temp_dir().join(format!("hero-rs-ipc-{}", process::id()));
+        let socket = dir.join("hero-rs.sock");
+        let (instance, listener) = Instance::acquire(&socket).unwrap();
+        assert!(Instance::acquire(&socket)
+            .err()
+            .unwrap()
+            .contains(&process::id().to_string()));
+        let events = Arc::new(EventBus::new());
+        serve(listener, Arc::new(echo), Arc::clone(&events));
+
+        assert_eq!(call(&socket, "echo", json!([1, 2])).unwrap(), json!([1, 2]));
+        assert_eq!(call(&socket, "echo", Value::Null).unwrap(), Value::Null);
+        assert!(call(&socket, "fail", Value::Null)
+            .unwrap_err()
+            .contains("失败"));
+
+        // 订阅之后收到发布的事件；订阅生效之前发布的事件会丢失，所以重复发布
+        let (tx, rx) = mpsc::channel();
+        let subscriber = socket.clone();
+        thread::spawn(move || subscribe(&subscriber, |event| tx.send(event).unwrap()));
+        let event = loop {
+            events.publish(AppEvent::PauseToggled { paused: true });
+            if let Ok(event) = rx.recv_timeout(Duration::from_millis(10)) {
+                break event;
+            }
+        };
+        assert_eq!(event["type"], "pause_toggled");
+        assert_eq!(event["paused"], true);
+
+        drop(instance);
+        assert!(!socket.exists());
+        assert!(!pid_path(&socket).exists());
+        assert!(call(&socket, "echo", Value::Null).is_err());
+        let _ = fs::remove_dir(&dir);
+    }
+}
@@ -0,0 +1,17 @@
+pub mod clock;
+pub mod config;
+pub mod doctor;
+pub mod engine;
+pub mod events;
+pub mod fuzzy;
+pub mod hooks;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod index;
+pub mod input;
+#[cfg(unix)]
+pub mod ipc;
+pub mod log;
+pub mod pattern;
+pub mod profile;
+pub mod record;
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+// 日志级别，输出不低于当前级别的日志
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, clap::ValueEnum)]
+pub enum Level {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    // 包括每个输入事件
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+// 日志输出，每次一行（不含换行符）
+pub type Output = Box<dyn FnMut(String) + Send>;
+
+static OUTPUT: Mutex<Option<Output>> = Mutex::new(None);
+
+// 替换日志输出，例如交互式命令行把日志显示在提示符上方；None 恢复为标准输出
+pub fn set_output(output: Option<Output>) {
+    *OUTPUT.lock().unwrap() = output;
+}
+
+// 供日志宏使用
+pub fn write(args: fmt::Arguments) {
+    match OUTPUT.lock().unwrap().as_mut() {
+        Some(output) => output(args.to_string()),
+        None => println!("{}", args),
+    }
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Error) {
+            $crate::log::write(format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Warn) {
+            $crate::log::write(format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Info) {
+            $crate::log::write(format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Debug) {
+            $crate::log::write(format_args!($($arg)*));
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_output() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let captured = Arc::clone(&lines);
+        set_output(Some(Box::new(move |line| {
+            captured.lock().unwrap().push(line)
+        })));
+        crate::info!("连招 {} 已执行", "QWE");
+        crate::debug!("默认级别不输出调试日志");
+        set_output(None);
+        crate::info!("恢复为标准输出");
+
+        let lines = lines.lock().unwrap();
+        assert!(lines.contains(&"连招 QWE 已执行".to_string()));
+        assert!(!lines
+            .iter()
+            .any(|line| line.contains("调试") || line.contains("恢复")));
+    }
+}
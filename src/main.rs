@@ -1,708 +1,2684 @@
-mod config;
-
-use config::{TriggerType};
-use rdev::{listen, simulate, Button, Event, EventType, Key, SimulateError};
-use std::collections::{HashMap, VecDeque};
-use std::io::Write;
-use std::sync::{mpsc, Arc, RwLock};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use hero_rs::{
+    clock::{Clock, SystemClock},
+    config::{self, ComboConfig, Config, StepConfig, TriggerType},
+    debug, doctor,
+    engine::{Combo, Engine, Outcome, Verdict},
+    error,
+    events::{AppEvent, EventBus},
+    fuzzy::{self, Lookup},
+    hooks::Hooks,
+    info,
+    input::{
+        describe, event_at, DeviceInfo, DryRunSink, InputError, InputSink, InputSource, RdevGrab,
+        RdevListen, RdevSink,
+    },
+    log::{self, Level},
+    pattern::{key_name, parse_key},
+    profile::{self, ProfileStore},
+    record::{self, Recorder},
+    warn,
+};
+#[cfg(target_os = "linux")]
+use hero_rs::input::evdev::EvdevGrab;
+#[cfg(feature = "http")]
+use hero_rs::http;
+#[cfg(unix)]
+use hero_rs::ipc::{self, RpcError};
+use rdev::{Event, EventType, Key};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Editor, ExternalPrinter, Helper};
+use std::cell::Cell;
+#[cfg(any(unix, feature = "http"))]
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
 use std::thread;
-use std::time::{Duration, Instant};
-
-// 定义按键类型，可以是键盘按键或鼠标按钮
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum ActionKey {
-    Keyboard(Key),
-    Mouse(Button),
-}
-
-// 定义操作类型：按下或释放
-#[derive(Debug, Clone, PartialEq)]
-enum ActionType {
-    Press,
-    Release,
-}
-
-// 定义一个操作，包含按键和操作类型
-#[derive(Debug, Clone, PartialEq)]
-struct Action {
-    key: ActionKey,
-    action_type: ActionType,
-    timestamp: Instant,
-}
-
-// 定义连招序列
-#[derive(Debug, Clone)]
-pub struct Combo {
-    name: String,
-    sequence: Vec<ActionKey>,
-    delays: Vec<(u64, u64)>, // (按下前延迟, 按下后延迟)
-    trigger: TriggerType,
-    block_original_input: bool,
-    active: bool,
-}
-
-impl Combo {
-    pub fn new(
-        name: String, 
-        sequence: Vec<ActionKey>, 
-        delays: Vec<(u64, u64)>,
-        trigger: TriggerType,
-        block_original_input: bool
-    ) -> Self {
-        Combo {
-            name,
-            sequence,
-            delays,
-            trigger,
-            block_original_input,
-            active: true,
+use std::time::Duration;
+#[cfg(any(unix, feature = "http"))]
+use std::time::Instant;
+
+
+// 运行统计，供控制接口使用
+#[cfg(any(unix, feature = "http"))]
+#[derive(Debug, Default)]
+struct Stats {
+    events: u64, // 处理的按键和鼠标按键事件数
+    keys_blocked: u64, // 被连招屏蔽的原始按键数
+    executions: HashMap<String, u64>, // 每个连招的执行次数，包括手动执行
+}
+
+#[cfg(any(unix, feature = "http"))]
+impl Stats {
+    // 作为事件总线的同步监听者计数
+    fn record(&mut self, event: &AppEvent) {
+        match event {
+            AppEvent::InputObserved { .. } => self.events += 1,
+            AppEvent::KeyBlocked { .. } => self.keys_blocked += 1,
+            AppEvent::ComboTriggered { combo, .. } => *self.executions.entry(combo.clone()).or_default() += 1,
+            _ => {}
         }
     }
+}
 
-    // 执行连招
-    fn execute(&self) -> Result<(), SimulateError> {
-        println!("执行连招: {}", self.name);
-        
-        for (idx, key) in self.sequence.iter().enumerate() {
-            // 获取当前按键的延迟配置
-            let (before_delay, after_delay) = if idx < self.delays.len() {
-                self.delays[idx]
-            } else {
-                (0, 0)
-            };
-            
-            // 执行前延迟
-            if before_delay > 0 {
-                thread::sleep(Duration::from_millis(before_delay));
-            }
-            
-            // 按下按键
-            match key {
-                ActionKey::Keyboard(k) => {
-                    simulate(&EventType::KeyPress(*k))?;
-                    thread::sleep(Duration::from_millis(50)); // 短暂延迟确保按键被识别
-                    simulate(&EventType::KeyRelease(*k))?;
-                }
-                ActionKey::Mouse(b) => {
-                    simulate(&EventType::ButtonPress(*b))?;
-                    thread::sleep(Duration::from_millis(50)); // 短暂延迟确保按键被识别
-                    simulate(&EventType::ButtonRelease(*b))?;
+// 把事件写到日志。连招开始和失败在发布者的线程中同步写出，不会因为队列已满而丢失；
+// 调试日志（每个按键都有）只在启用时订阅，在单独的线程中写出，不占用处理输入的时间，
+// 事件总线释放后线程退出
+fn spawn_event_logger(events: &EventBus) -> Option<thread::JoinHandle<()>> {
+    events.listen(Box::new(|event| match event {
+        AppEvent::ComboStarted { combo } => info!("执行连招: {}", combo),
+        AppEvent::ComboCancelled { combo, reason } => error!("执行连招 {} 失败: {}", combo, reason),
+        _ => {}
+    }));
+    if !log::enabled(Level::Debug) {
+        return None;
+    }
+    let receiver = events.subscribe();
+    Some(thread::spawn(move || {
+        for envelope in receiver {
+            match envelope.event {
+                AppEvent::InputObserved { input, device, blocked } => {
+                    let device = device.map(|device| format!("（{}）", device.name)).unwrap_or_default();
+                    let blocked = if blocked { "，已屏蔽" } else { "" };
+                    debug!("{}{}{}", describe(&input), device, blocked);
                 }
+                AppEvent::ComboTriggered { combo, key: Some(key) } => debug!("按键 {} 触发连招 {}", key, combo),
+                AppEvent::ComboStepExecuted { combo, step, key } => debug!("连招 {} 第 {} 步: {}", combo, step, key),
+                AppEvent::KeyBlocked { key } => debug!("屏蔽原始按键: {}", key),
+                _ => {}
             }
-            
-            // 执行后延迟
-            if after_delay > 0 {
-                thread::sleep(Duration::from_millis(after_delay));
-            }
         }
+    }))
+}
+
+// 应用状态
+struct AppState {
+    engine: Engine, // 处理输入事件的核心
+    sink: Arc<dyn InputSink>, // 连招按键的注入端
+    can_block: bool, // 当前输入模式能否屏蔽原始按键
+    allow_unblocked: bool, // 无法屏蔽时仍然加载需要屏蔽原始按键的连招
+    profile_store: Option<ProfileStore>, // 切换英雄时记录到状态文件，下次启动时恢复
+    active_overrides: HashMap<String, bool>, // 运行时启用/禁用的连招，切换英雄后仍然有效
+    input_mode: Option<&'static str>, // 当前使用的输入源
+    running: Arc<Mutex<Vec<String>>>, // 正在执行的连招
+    config_path: Option<PathBuf>, // 配置文件路径，使用内置默认配置时为 None
+    #[cfg(any(unix, feature = "http"))]
+    started: Instant,
+    #[cfg(any(unix, feature = "http"))]
+    stats: Arc<Mutex<Stats>>,
+    hooks: Option<Hooks>, // 配置的钩子，只在 run 中启用
+}
+
+impl AppState {
+    fn new(config: Config, sink: Arc<dyn InputSink>, clock: Arc<dyn Clock>) -> Self {
+        // 输入和状态变化的事件，日志、统计和事件流都从这里订阅
+        let events = Arc::new(EventBus::new());
+        #[cfg(any(unix, feature = "http"))]
+        let stats = Arc::new(Mutex::new(Stats::default()));
+        #[cfg(any(unix, feature = "http"))]
+        {
+            let counter = Arc::clone(&stats);
+            events.listen(Box::new(move |event| counter.lock().unwrap().record(event)));
+        }
+        spawn_event_logger(&events);
         
-        Ok(())
+        #[cfg(any(unix, feature = "http"))]
+        let started = clock.now();
+        let mut state = AppState {
+            engine: Engine::new(config, clock, events),
+            sink,
+            can_block: true,
+            allow_unblocked: false,
+            profile_store: None,
+            active_overrides: HashMap::new(),
+            input_mode: None,
+            running: Arc::new(Mutex::new(Vec::new())),
+            config_path: None,
+            #[cfg(any(unix, feature = "http"))]
+            started,
+            #[cfg(any(unix, feature = "http"))]
+            stats,
+            hooks: None,
+        };
+        state.load_combos();
+        state
     }
 
-    // 检查是否应该屏蔽用户输入
-    fn should_block_input(&self, key: Key, key_sequence: &[Key], timeout_ms: u64) -> bool {
-        if !self.block_original_input {
-            return false;
-        }
-
-        match &self.trigger {
-            TriggerType::KeySequence { keys, timeout_ms: trigger_timeout } => {
-                if keys.len() > 1 && keys.last().unwrap() == &key &&
-                    key_sequence.len() >= keys.len() - 1 {
-                    // 检查序列的前n-1个键是否匹配
-                    let start_idx = key_sequence.len() - (keys.len() - 1);
-                    let mut matches = true;
-                    for (i, &k) in keys.iter().take(keys.len() - 1).enumerate() {
-                        if key_sequence[start_idx + i] != k {
-                            matches = false;
-                            break;
-                        }
-                    }
-
-                    // 检查序列是否在超时时间内
-                    if matches && timeout_ms <= *trigger_timeout {
-                        return true;
-                    }
+    // 根据当前英雄重新加载连招：全局连招 + 英雄特定连招
+    fn load_combos(&mut self) {
+        let hold_ms = self.engine.config.general.default_delay_ms;
+        
+        let mut configs: Vec<&ComboConfig> = self.engine.config.global_combos.iter().collect();
+        if let Some(champion_name) = &self.engine.current_champion {
+            match self.engine.config.find_champion(champion_name) {
+                Some((_, champion)) => configs.extend(&champion.combos),
+                None => println!("配置中没有英雄 {} 的特定连招", champion_name),
+            }
+        }
+        
+        // 无法屏蔽原始按键时，需要屏蔽的连招会让原始按键和连招同时生效，默认不加载
+        let refuse_blocking = !self.can_block && !self.allow_unblocked;
+        let combos = configs
+            .into_iter()
+            .filter(|combo| {
+                let refused = refuse_blocking && combo.block_original_input;
+                if refused {
+                    println!("跳过连招 {}: 当前输入模式无法屏蔽原始按键（使用 --allow-unblocked 仍然加载）", combo.name);
                 }
-            },
-            _ => {}
+                !refused
+            })
+            .map(|combo| {
+                let mut combo = Combo::from_config(combo, hold_ms);
+                if let Some(&active) = self.active_overrides.get(&combo.name) {
+                    combo.active = active;
+                }
+                combo
+            })
+            .collect();
+        self.engine.set_combos(combos);
+    }
+
+    // 切换输入模式后重新加载连招
+    fn set_can_block(&mut self, can_block: bool) {
+        if self.can_block != can_block {
+            self.can_block = can_block;
+            self.load_combos();
         }
+    }
+
+    // 设置当前英雄并加载相应的连招
+    fn set_champion(&mut self, champion_name: String) {
+        self.engine.current_champion = Some(champion_name);
+        self.load_combos();
+        self.save_profile();
+        self.engine.events.publish(AppEvent::ChampionSwitched { champion: self.engine.current_champion.clone() });
         
-        false
+        println!("已切换到英雄: {}", self.engine.current_champion.as_ref().unwrap_or(&"无".to_string()));
+        self.print_combos();
     }
-}
 
-// 按键历史记录
-struct KeyHistory {
-    actions: VecDeque<Action>,
-    pressed_keys: HashMap<ActionKey, Instant>,
-    recent_keys: VecDeque<(Key, Instant)>,
-    max_size: usize,
-    sequence_max_size: usize,
-    history_timeout: Duration,
-}
+    // 清除当前英雄，只使用全局连招
+    fn clear_champion(&mut self) {
+        self.engine.current_champion = None;
+        self.load_combos();
+        self.save_profile();
+        self.engine.events.publish(AppEvent::ChampionSwitched { champion: None });
+        
+        println!("已切换到全局配置");
+        self.print_combos();
+    }
 
-impl KeyHistory {
-    fn new(max_size: usize, sequence_max_size: usize, history_timeout_ms: u64) -> Self {
-        KeyHistory {
-            actions: VecDeque::with_capacity(max_size),
-            pressed_keys: HashMap::new(),
-            recent_keys: VecDeque::with_capacity(sequence_max_size),
-            max_size,
-            sequence_max_size,
-            history_timeout: Duration::from_millis(history_timeout_ms),
+    // 记录当前英雄，下次启动时恢复
+    fn save_profile(&self) {
+        if let Some(store) = &self.profile_store {
+            if let Err(e) = store.save(self.engine.current_champion.as_deref()) {
+                warn!("无法保存当前英雄到 {}: {}", store.path().display(), e);
+            }
         }
     }
 
-    fn add_action(&mut self, action: Action) {
-        // 更新按下的键
-        if action.action_type == ActionType::Press {
-            self.pressed_keys.insert(action.key.clone(), action.timestamp);
-        } else if action.action_type == ActionType::Release {
-            self.pressed_keys.remove(&action.key);
+    // 显示加载的连招
+    fn print_combos(&self) {
+        println!("已加载的连招:");
+        for (i, combo) in self.engine.combos.iter().enumerate() {
+            let status = if combo.active { "" } else { "（已禁用）" };
+            println!("  {}. {}{} - 触发条件: {:?}", i + 1, combo.name, status, combo.trigger);
         }
-        
-        // 如果是键盘按键，添加到最近按下的键序列中
-        if let ActionKey::Keyboard(key) = &action.key {
-            if action.action_type == ActionType::Press {
-                self.recent_keys.push_back((*key, action.timestamp));
-                
-                // 保持最近按键序列不超过最大大小
-                if self.recent_keys.len() > self.sequence_max_size {
-                    self.recent_keys.pop_front();
-                }
+    }
+
+    // 按名称模糊查找已加载的连招，找不到或不唯一时输出提示
+    fn lookup_combo(&self, query: &str) -> Option<usize> {
+        self.find_combo_index(query).map_err(|e| println!("{}", e)).ok()
+    }
+
+    // 按名称模糊查找已加载的连招，找不到或不唯一时返回说明
+    fn find_combo_index(&self, query: &str) -> Result<usize, String> {
+        match fuzzy::lookup(query, self.engine.combos.iter().map(|combo| combo.name.as_str())) {
+            Lookup::Found(idx) => Ok(idx),
+            Lookup::Ambiguous(indices) => {
+                let names: Vec<&str> = indices.iter().map(|&idx| self.engine.combos[idx].name.as_str()).collect();
+                Err(format!("有多个连招匹配 {}: {}", query, names.join(", ")))
             }
+            Lookup::NotFound => Err(format!("没有找到连招 {}（输入 list 查看已加载的连招）", query)),
         }
+    }
+
+    // 显示连招的详细配置
+    fn show_combo(&self, idx: usize) {
+        let combo = &self.engine.combos[idx];
+        let yes_no = |value| if value { "是" } else { "否" };
+        println!("连招: {}", combo.name);
+        println!("  状态: {}", if combo.active { "启用" } else { "已禁用" });
+        println!("  触发条件: {:?}", combo.trigger);
+        println!("  优先级: {}，具体程度: {}", combo.priority, combo.specificity);
+        println!("  屏蔽原始按键: {}", yes_no(combo.block_original_input));
         
-        // 移除超时的操作
-        let now = Instant::now();
-        while let Some(front) = self.actions.front() {
-            if now.duration_since(front.timestamp) > self.history_timeout {
-                self.actions.pop_front();
-            } else {
-                break;
-            }
+        let conditions = &combo.conditions;
+        if !conditions.held.is_empty() {
+            println!("  需要按住: {:?}", conditions.held);
+        }
+        if !conditions.not_held.is_empty() {
+            println!("  需要松开: {:?}", conditions.not_held);
+        }
+        if !conditions.champions.is_empty() {
+            println!("  限定英雄: {}", conditions.champions.join(", "));
+        }
+        if !conditions.layers.is_empty() {
+            println!("  需要的层: {}", conditions.layers.join(", "));
+        }
+        if !conditions.devices.is_empty() {
+            println!("  限定设备: {:?}", conditions.devices);
+        }
+        if conditions.allow_when_paused {
+            println!("  暂停时仍然触发: 是");
+        }
+        if conditions.allow_in_chat {
+            println!("  聊天框打开时仍然触发: 是");
         }
-
-        // 添加新操作
-        self.actions.push_back(action);
         
-        // 如果超出最大大小，移除最旧的
-        if self.actions.len() > self.max_size {
-            self.actions.pop_front();
+        println!("  步骤（每个按键按住 {} 毫秒）:", combo.hold_ms);
+        for (i, (key, (before, after))) in combo.sequence.iter().zip(&combo.delays).enumerate() {
+            println!("    {}. {:?}（之前等待 {} 毫秒，之后等待 {} 毫秒）", i + 1, key, before, after);
         }
     }
 
-    // 获取最近按下的按键序列
-    fn get_recent_key_sequence(&self) -> Vec<Key> {
-        self.recent_keys.iter().map(|(key, _)| *key).collect()
+    // 绑定按键到命令，按下该按键时执行命令
+    fn bind_key(&mut self, key: Key, command: &str) {
+        println!("已绑定 {} -> {}", key_name(key), command);
+        self.engine.bindings.insert(key, command.to_string());
     }
 
-    // 计算最近两个按键之间的时间间隔（毫秒）
-    fn get_last_key_interval(&self) -> u64 {
-        if self.recent_keys.len() < 2 {
-            return 0;
+    fn unbind_key(&mut self, key: Key) {
+        match self.engine.bindings.remove(&key) {
+            Some(command) => println!("已解除绑定 {} -> {}", key_name(key), command),
+            None => println!("按键 {} 没有绑定命令", key_name(key)),
         }
-        
-        let len = self.recent_keys.len();
-        let (_, latest_time) = self.recent_keys[len - 1];
-        let (_, prev_time) = self.recent_keys[len - 2];
-        
-        latest_time.duration_since(prev_time).as_millis() as u64
     }
 
-    // 检查是否匹配按键修饰符组合
-    fn matches_key_modifier(&self, modifier: Key, key: Key) -> bool {
-        // 检查修饰键是否已按下
-        let modifier_pressed = self.pressed_keys.contains_key(&ActionKey::Keyboard(modifier));
-        
-        // 检查主键是否最近按下
-        if self.recent_keys.len() > 0 {
-            let (last_key, _) = self.recent_keys.back().unwrap();
-            return modifier_pressed && *last_key == key;
+    fn print_bindings(&self) {
+        if self.engine.bindings.is_empty() {
+            println!("没有绑定命令的按键");
+            return;
         }
-        
-        false
+        let mut bindings: Vec<_> = self.engine.bindings.iter().map(|(key, command)| (key_name(*key), command)).collect();
+        bindings.sort();
+        println!("按键绑定:");
+        for (key, command) in bindings {
+            println!("  {} -> {}", key, command);
+        }
+    }
+
+    // 启用或禁用连招，切换英雄后仍然保持
+    fn set_combo_active(&mut self, idx: usize, active: bool) {
+        let combo = &mut self.engine.combos[idx];
+        combo.active = active;
+        self.active_overrides.insert(combo.name.clone(), active);
+        println!("已{}连招: {}", if active { "启用" } else { "禁用" }, combo.name);
     }
 
-    // 检查是否匹配一个按键序列
-    fn matches_key_sequence(&self, sequence: &[Key], timeout_ms: u64) -> bool {
-        if sequence.len() > self.recent_keys.len() {
-            return false;
+    // 列出配置中的英雄，标出当前英雄
+    fn print_profiles(&self) {
+        if self.engine.config.champion_specific.is_empty() {
+            println!("配置中没有英雄特定连招");
+            return;
         }
-        
-        // 获取要匹配的最后N个按键
-        let keys = self.get_recent_key_sequence();
-        let start = keys.len() - sequence.len();
-        
-        // 检查是否序列匹配
-        for (i, &k) in sequence.iter().enumerate() {
-            if keys[start + i] != k {
-                return false;
+        let mut champions: Vec<_> = self.engine.config.champion_specific.iter().collect();
+        champions.sort_by_key(|(id, _)| id.as_str());
+        println!("英雄配置:");
+        for (id, champion) in champions {
+            let marker = if self.engine.is_current_champion(id) { "*" } else { " " };
+            println!("  {} {}（{}）- {} 个连招", marker, id, champion.name, champion.combos.len());
+        }
+    }
+
+    // 显示运行状态
+    fn print_status(&self) {
+        println!("当前英雄: {}", self.engine.current_champion.as_deref().unwrap_or("无（使用全局连招）"));
+        println!("连招: {}", if self.engine.paused { "已暂停" } else { "运行中" });
+        match self.input_mode {
+            Some(mode) => {
+                let block = if self.can_block { "可以" } else { "无法" };
+                println!("输入模式: {}（{}屏蔽原始按键）", mode, block);
             }
+            None => println!("输入模式: 未启动"),
         }
-        
-        // 检查时间间隔是否在允许范围内
-        if self.recent_keys.len() >= sequence.len() {
-            let latest_idx = self.recent_keys.len() - 1;
-            let earliest_idx = latest_idx - (sequence.len() - 1);
-            
-            let (_, latest_time) = self.recent_keys[latest_idx];
-            let (_, earliest_time) = self.recent_keys[earliest_idx];
-            
-            let elapsed = latest_time.duration_since(earliest_time).as_millis() as u64;
-            return elapsed <= timeout_ms;
+        if self.engine.dry_run {
+            println!("演习模式: 连招只记录到日志");
+        }
+        if self.engine.chat_open {
+            println!("聊天框: 已打开");
+        }
+        if !self.engine.layers.is_empty() {
+            let mut layers: Vec<&str> = self.engine.layers.iter().map(String::as_str).collect();
+            layers.sort();
+            println!("激活的层: {}", layers.join(", "));
+        }
+        let active = self.engine.combos.iter().filter(|combo| combo.active).count();
+        println!("已加载连招: {} 个（{} 个启用）", self.engine.combos.len(), active);
+        let running = self.running.lock().unwrap();
+        if running.is_empty() {
+            println!("正在执行的连招: 无");
+        } else {
+            println!("正在执行的连招: {}", running.join(", "));
         }
-        
-        false
     }
-}
 
-// 应用状态
-struct AppState {
-    history: KeyHistory,
-    combos: Vec<Combo>,
-    blocked_keys: HashMap<Key, Instant>,
-    current_champion: Option<String>,
-}
+    // 运行状态，供控制接口使用
+    #[cfg(any(unix, feature = "http"))]
+    fn status(&self) -> Value {
+        let mut layers: Vec<&str> = self.engine.layers.iter().map(String::as_str).collect();
+        layers.sort();
+        json!({
+            "pid": std::process::id(),
+            "champion": self.engine.current_champion,
+            "paused": self.engine.paused,
+            "input_mode": self.input_mode,
+            "can_block": self.can_block,
+            "dry_run": self.engine.dry_run,
+            "chat_open": self.engine.chat_open,
+            "layers": layers,
+            "combos": self.engine.combos.len(),
+            "active_combos": self.engine.combos.iter().filter(|combo| combo.active).count(),
+            "running": self.running.lock().unwrap().clone(),
+        })
+    }
 
-impl AppState {
-    fn new() -> Self {
-        // 硬编码的默认设置
-        let history_size = 20;
-        let history_timeout_ms = 2000;
-        
-        // 创建按键历史
-        let history = KeyHistory::new(
-            history_size,
-            10, // 按键序列最大长度
-            history_timeout_ms,
-        );
-        
-        // 硬编码的连招配置 - 全局连招
-        let mut combos = Vec::new();
-        
-        // 全局连招：按下 Tab 键执行 A -> 25ms -> 鼠标左键
-        combos.push(Combo::new(
-            "Tab触发A+左键".to_string(),
-            vec![
-                ActionKey::Keyboard(Key::KeyA),
-                ActionKey::Mouse(Button::Left)
-            ],
-            vec![(0, 25), (0, 0)], // (按下前延迟, 按下后延迟)
-            TriggerType::SingleKey(Key::Tab),
-            false
-        ));
-        
-        AppState {
-            history,
-            combos,
-            blocked_keys: HashMap::new(),
-            current_champion: None,
+    // 运行统计，供控制接口使用
+    #[cfg(any(unix, feature = "http"))]
+    fn stats(&self) -> Value {
+        let stats = self.stats.lock().unwrap();
+        json!({
+            "uptime_secs": self.engine.clock.now().duration_since(self.started).as_secs(),
+            "events": stats.events,
+            "keys_blocked": stats.keys_blocked,
+            "combos_executed": stats.executions.values().sum::<u64>(),
+            "executions": stats.executions,
+        })
+    }
+
+    // 配置中的英雄，供控制接口使用
+    #[cfg(any(unix, feature = "http"))]
+    fn profile_list(&self) -> Value {
+        let mut champions: Vec<_> = self.engine.config.champion_specific.iter().collect();
+        champions.sort_by_key(|(id, _)| id.as_str());
+        champions
+            .into_iter()
+            .map(|(id, champion)| {
+                json!({
+                    "id": id,
+                    "name": champion.name,
+                    "combos": champion.combos.len(),
+                    "current": self.engine.is_current_champion(id),
+                })
+            })
+            .collect()
+    }
+
+    // 已加载的连招，供控制接口使用
+    #[cfg(any(unix, feature = "http"))]
+    fn combo_list(&self) -> Value {
+        self.engine.combos
+            .iter()
+            .map(|combo| json!({ "name": combo.name, "active": combo.active, "trigger": combo.trigger.to_text() }))
+            .collect()
+    }
+
+    // 重新读取配置文件，保持当前英雄和运行时启用/禁用的连招
+    fn reload_config(&mut self) -> Result<usize, String> {
+        let path = self.config_path.clone().ok_or("使用的是内置默认配置，没有可以重新加载的配置文件")?;
+        self.engine.config = Config::load(&path)?;
+        self.load_combos();
+        if let Some(hooks) = &self.hooks {
+            hooks.update(&self.engine.config);
         }
+        println!("已重新加载配置文件 {}（{} 个连招）", path.display(), self.engine.combos.len());
+        Ok(self.engine.combos.len())
     }
 
-    // 设置当前英雄并加载相应的连招
-    fn set_champion(&mut self, champion_name: String) {
-        self.current_champion = Some(champion_name.clone());
-        
-        // 清除现有的连招
-        self.combos.clear();
-        
-        // 添加全局连招
-        self.combos.push(Combo::new(
-            "Tab触发A+左键".to_string(),
-            vec![
-                ActionKey::Keyboard(Key::KeyA),
-                ActionKey::Mouse(Button::Left)
-            ],
-            vec![(0, 25), (0, 0)],
-            TriggerType::SingleKey(Key::Tab),
-            false
-        ));
-        
-        // 根据英雄添加特定连招
-        if champion_name.to_lowercase() == "yasuo" || champion_name.to_lowercase() == "亚索" {
-            // 亚索特定连招1: 按下E后150ms内按下R，屏蔽R执行QR
-            self.combos.push(Combo::new(
-                "亚索E+R触发QR".to_string(),
-                vec![
-                    ActionKey::Keyboard(Key::KeyQ),
-                    ActionKey::Keyboard(Key::KeyR)
-                ],
-                vec![(0, 50), (50, 0)],
-                TriggerType::KeySequence {
-                    keys: vec![Key::KeyE, Key::KeyR],
-                    timeout_ms: 150,
-                },
-                true // 屏蔽原始输入
-            ));
-            
-            // 亚索特定连招2: 按下E后150ms内按下D，屏蔽D执行QD
-            self.combos.push(Combo::new(
-                "亚索E+D触发QD".to_string(),
-                vec![
-                    ActionKey::Keyboard(Key::KeyQ),
-                    ActionKey::Keyboard(Key::KeyD)
-                ],
-                vec![(0, 50), (50, 0)],
-                TriggerType::KeySequence {
-                    keys: vec![Key::KeyE, Key::KeyD],
-                    timeout_ms: 150,
-                },
-                true // 屏蔽原始输入
-            ));
-        }
-        
-        println!("已切换到英雄: {}", self.current_champion.as_ref().unwrap_or(&"无".to_string()));
-        
-        // 显示加载的连招
-        println!("已加载的连招:");
-        for (i, combo) in self.combos.iter().enumerate() {
-            println!("  {}. {} - 触发条件: {:?}", i + 1, combo.name, combo.trigger);
+    // 手动执行连招：不检查触发条件，包括 Manual 触发和已禁用的连招
+    fn run_combo(&self, idx: usize, delay: Duration) {
+        let combo = self.engine.combos[idx].clone();
+        if !combo.active {
+            println!("注意：连招 {} 已禁用，仍然手动执行", combo.name);
         }
+        self.spawn_combo(combo, None, delay);
     }
 
-    // 检查按键是否被屏蔽
-    fn is_key_blocked(&mut self, key: Key) -> bool {
-        // 清理过期的屏蔽
-        let now = Instant::now();
-        self.blocked_keys.retain(|_, time| {
-            now.duration_since(*time) < Duration::from_millis(500) // 按键屏蔽最多持续500毫秒
-        });
-        
-        self.blocked_keys.contains_key(&key)
+    // 当前英雄在配置中的键名，当前英雄不在配置中时为 None
+    fn current_champion_id(&self) -> Option<String> {
+        let name = self.engine.current_champion.as_ref()?;
+        self.engine.config.find_champion(name).map(|(id, _)| id.clone())
     }
 
-    // 屏蔽按键
-    fn block_key(&mut self, key: Key) {
-        self.blocked_keys.insert(key, Instant::now());
+    // 已加载的连招在配置中的定义，以及所属的英雄（全局连招为 None）
+    fn find_combo_config(&self, name: &str) -> Option<(Option<String>, ComboConfig)> {
+        if let Some(combo) = self.engine.config.global_combos.iter().find(|combo| combo.name == name) {
+            return Some((None, combo.clone()));
+        }
+        let id = self.current_champion_id()?;
+        let combo = self.engine.config.champion_specific[&id].combos.iter().find(|combo| combo.name == name)?;
+        Some((Some(id), combo.clone()))
     }
 
-    // 处理按键事件
-    fn handle_event(&mut self, event: &Event) -> Option<Event> {
-        match event.event_type {
-            EventType::KeyPress(key) => {
-                println!("Key pressed: {:?}", key);
-                
-                // 检查按键是否被屏蔽
-                if self.is_key_blocked(key) {
-                    println!("Key blocked: {:?}", key);
-                    return None; // 屏蔽按键
-                }
-                
-                let action = Action {
-                    key: ActionKey::Keyboard(key),
-                    action_type: ActionType::Press,
-                    timestamp: Instant::now(),
-                };
-                
-                // 更新按键历史
-                self.history.add_action(action);
-                
-                // 检查快捷键 - 切换英雄
-                // Shift+Y 切换到亚索
-                if key == Key::KeyY && self.history.matches_key_modifier(Key::ShiftLeft, Key::KeyY) {
-                    println!("检测到快捷键 Shift+Y，切换到亚索");
-                    self.set_champion("亚索".to_string());
-                    return Some(event.clone());
-                }
-                
-                // Shift+S 切换到无英雄（全局配置）
-                if key == Key::KeyS && self.history.matches_key_modifier(Key::ShiftLeft, Key::KeyS) {
-                    println!("检测到快捷键 Shift+S，切换到全局配置");
-                    self.current_champion = None;
-                    
-                    // 恢复默认连招
-                    self.combos.clear();
-                    self.combos.push(Combo::new(
-                        "Tab触发A+左键".to_string(),
-                        vec![
-                            ActionKey::Keyboard(Key::KeyA),
-                            ActionKey::Mouse(Button::Left)
-                        ],
-                        vec![(0, 25), (0, 0)],
-                        TriggerType::SingleKey(Key::Tab),
-                        false
-                    ));
-                    
-                    println!("已切换到全局配置");
-                    println!("已加载的连招:");
-                    for (i, combo) in self.combos.iter().enumerate() {
-                        println!("  {}. {} - 触发条件: {:?}", i + 1, combo.name, combo.trigger);
-                    }
-                    
-                    return Some(event.clone());
-                }
-                
-                // 获取最近的按键序列和时间间隔
-                let recent_keys = self.history.get_recent_key_sequence();
-                let last_interval = self.history.get_last_key_interval();
-                
-                // 先检查是否需要屏蔽输入
-                let mut block_needed = false;
-                let mut block_key_value = None;
-                
-                // 遍历所有连招，检查是否需要触发
-                let mut triggered_combo = None;
-                
-                for (idx, combo) in self.combos.iter().enumerate() {
-                    if !combo.active {
-                        continue;
-                    }
-                    
-                    // 检查是否匹配触发条件
-                    let should_trigger = match &combo.trigger {
-                        TriggerType::SingleKey(trigger_key) => {
-                            *trigger_key == key
-                        },
-                        TriggerType::KeySequence { keys, timeout_ms } => {
-                            if keys.last().unwrap() == &key {
-                                self.history.matches_key_sequence(keys, *timeout_ms)
-                            } else {
-                                false
-                            }
-                        },
-                        TriggerType::KeyModifier { modifier, key: trigger_key } => {
-                            *trigger_key == key && self.history.matches_key_modifier(*modifier, key)
-                        },
-                        TriggerType::Manual => false,
-                    };
-                    
-                    // 检查是否需要屏蔽输入，先记录下来稍后处理
-                    if combo.block_original_input && 
-                       combo.should_block_input(key, &recent_keys, last_interval) {
-                        // 记录需要屏蔽的按键
-                        block_needed = true;
-                        block_key_value = Some(key);
-                    }
-                    
-                    if should_trigger {
-                        triggered_combo = Some(idx);
-                        break;
-                    }
-                }
-                
-                // 现在处理屏蔽
-                if block_needed && block_key_value.is_some() {
-                    self.block_key(block_key_value.unwrap());
-                }
-                
-                // 如果触发了连招，执行它
-                if let Some(idx) = triggered_combo {
-                    let combo = self.combos[idx].clone();
-                    
-                    // 如果需要屏蔽原始输入
-                    if combo.block_original_input {
-                        self.block_key(key);
-                    }
-                    
-                    thread::spawn(move || {
-                        if let Err(e) = combo.execute() {
-                            println!("执行连招失败: {:?}", e);
-                        }
-                    });
-                }
-                
-                // 返回原始事件（除非被屏蔽）
-                if self.is_key_blocked(key) {
-                    None
+    // 新增或替换配置中的连招并重新加载，使用与加载配置文件时相同的检查规则
+    fn apply_combo_config(&mut self, champion: Option<&str>, old_name: Option<&str>, combo: ComboConfig) -> Result<(), String> {
+        let mut config = self.engine.config.clone();
+        let combos = match champion {
+            Some(id) => {
+                &mut config
+                    .champion_specific
+                    .get_mut(id)
+                    .ok_or_else(|| format!("配置中没有英雄 {}", id))?
+                    .combos
+            }
+            None => &mut config.global_combos,
+        };
+        match old_name.and_then(|old_name| combos.iter().position(|combo| combo.name == old_name)) {
+            Some(pos) => combos[pos] = combo,
+            None => combos.push(combo),
+        }
+        config.validate()?;
+        self.engine.config = config;
+        self.load_combos();
+        Ok(())
+    }
+
+    // 命令行的补全候选：命令名，以及命令参数中的连招名和英雄名
+    fn completions(&self, line: &str) -> Vec<String> {
+        let Some((command, arg)) = line.split_once(' ') else {
+            return fuzzy::complete(line, COMMANDS.iter().copied())
+                .into_iter()
+                .map(String::from)
+                .collect();
+        };
+        let mut names: Vec<&str> = match command {
+            "show" | "enable" | "disable" | "toggle" | "run" | "edit" => {
+                self.engine.combos.iter().map(|combo| combo.name.as_str()).collect()
+            }
+            "champion" => self
+                .engine.config
+                .champion_specific
+                .iter()
+                .flat_map(|(id, champion)| [id.as_str(), champion.name.as_str()])
+                .collect(),
+            _ => Vec::new(),
+        };
+        names.sort();
+        names.dedup();
+        fuzzy::complete(arg.trim_start(), names)
+            .into_iter()
+            .map(|name| format!("{} {}", command, name))
+            .collect()
+    }
+
+    // 在后台线程中执行连招（可以先倒计时），执行期间记录在 running 中
+    // key 是触发连招的按键，手动执行时为 None
+    fn spawn_combo(&self, combo: Combo, key: Option<Key>, delay: Duration) {
+        let sink = Arc::clone(&self.sink);
+        let clock = Arc::clone(&self.engine.clock);
+        let running = Arc::clone(&self.running);
+        let events = Arc::clone(&self.engine.events);
+        running.lock().unwrap().push(combo.name.clone());
+        events.publish(AppEvent::ComboTriggered { combo: combo.name.clone(), key: key.map(key_name) });
+        thread::spawn(move || {
+            countdown(clock.as_ref(), &combo.name, delay);
+            events.publish(AppEvent::ComboStarted { combo: combo.name.clone() });
+            match combo.execute(sink.as_ref(), clock.as_ref(), &events) {
+                Ok(()) => events.publish(AppEvent::ComboCompleted { combo: combo.name.clone() }),
+                Err(e) => events.publish(AppEvent::ComboCancelled { combo: combo.name.clone(), reason: e.to_string() }),
+            }
+            let mut running = running.lock().unwrap();
+            if let Some(pos) = running.iter().position(|name| *name == combo.name) {
+                running.remove(pos);
+            }
+        });
+    }
+
+    // 解释某个按键最近一次的匹配结果
+    fn explain(&self, key: Key) {
+        let Some(resolution) = self.engine.resolutions.get(&key) else {
+            println!("还没有按键 {:?} 的匹配记录", key);
+            return;
+        };
+        
+        if let Some(time) = resolution.time {
+            let elapsed = self.engine.clock.now().duration_since(time);
+            println!("按键 {:?} 最近一次按下于 {} 毫秒前", key, elapsed.as_millis());
+        }
+        println!("最近的按键序列: {:?}", self.engine.history.recent_keys().collect::<Vec<_>>());
+        
+        if resolution.reports.is_empty() {
+            println!("  没有连招的触发条件与该按键匹配");
+            return;
+        }
+        
+        for report in &resolution.reports {
+            let combo = &self.engine.combos[report.combo];
+            let detail = match &report.verdict {
+                Verdict::Inactive => "未启用".to_string(),
+                Verdict::ConditionFailed(failure) => format!("条件不满足：{}", failure),
+                Verdict::ModifierNotHeld(modifier) => format!("修饰键 {:?} 未按住", modifier),
+                Verdict::Matched => format!(
+                    "匹配（优先级 {}，具体程度 {}）",
+                    combo.priority, combo.specificity
+                ),
+            };
+            let fired = if resolution.fired.contains(&report.combo) { " <- 已触发" } else { "" };
+            println!("  {}: {}{}", combo.name, detail, fired);
+        }
+        
+        // 说明胜出的原因
+        let matched: Vec<usize> = resolution
+            .reports
+            .iter()
+            .filter(|report| matches!(report.verdict, Verdict::Matched))
+            .map(|report| report.combo)
+            .collect();
+        match resolution.fired.as_slice() {
+            [] => println!("没有连招被触发"),
+            _ if matched.len() == 1 => println!("只有一个连招匹配"),
+            _ if self.engine.config.general.fire_all_matches => {
+                println!("已启用 fire_all_matches，所有匹配的连招都被触发")
+            }
+            [winner, ..] => {
+                let winner_combo = &self.engine.combos[*winner];
+                let runner_up = matched
+                    .iter()
+                    .filter(|&&idx| idx != *winner)
+                    .map(|&idx| &self.engine.combos[idx])
+                    .max_by_key(|combo| (combo.priority, combo.specificity))
+                    .unwrap();
+                let reason = if winner_combo.priority != runner_up.priority {
+                    "优先级更高"
+                } else if winner_combo.specificity != runner_up.specificity {
+                    "触发条件更具体"
                 } else {
-                    Some(event.clone())
-                }
-            },
-            EventType::KeyRelease(key) => {
-                println!("Key released: {:?}", key);
-                
-                // 检查按键是否被屏蔽
-                if self.is_key_blocked(key) {
-                    println!("Key release blocked: {:?}", key);
-                    return None; // 屏蔽按键
-                }
-                
-                let action = Action {
-                    key: ActionKey::Keyboard(key),
-                    action_type: ActionType::Release,
-                    timestamp: Instant::now(),
-                };
-                
-                self.history.add_action(action);
-                Some(event.clone())
-            },
-            EventType::ButtonPress(button) => {
-                println!("Mouse button pressed: {:?}", button);
-                
-                let action = Action {
-                    key: ActionKey::Mouse(button),
-                    action_type: ActionType::Press,
-                    timestamp: Instant::now(),
+                    "优先级和具体程度相同，在配置中排在前面"
                 };
-                
-                self.history.add_action(action);
-                Some(event.clone())
-            },
-            EventType::ButtonRelease(button) => {
-                println!("Mouse button released: {:?}", button);
-                
-                let action = Action {
-                    key: ActionKey::Mouse(button),
-                    action_type: ActionType::Release,
-                    timestamp: Instant::now(),
-                };
-                
-                self.history.add_action(action);
-                Some(event.clone())
-            },
-            _ => Some(event.clone()),
+                println!("选择 {} 的原因: {}", winner_combo.name, reason);
+            }
         }
     }
-}
 
-// 处理命令
-fn handle_command(cmd: &str, state: Arc<RwLock<AppState>>) {
-    let cmd = cmd.trim();
-    
-    if cmd.starts_with("champion") {
-        let parts: Vec<&str> = cmd.splitn(2, ' ').collect();
-        if parts.len() == 2 {
-            let champion_name = parts[1].to_string();
-            let mut state = state.write().unwrap();
-            state.set_champion(champion_name);
+    // 暂停或恢复连招
+    fn set_paused(&mut self, paused: bool) {
+        if self.engine.paused != paused {
+            self.engine.events.publish(AppEvent::PauseToggled { paused });
+        }
+        self.engine.paused = paused;
+        if paused {
+            println!("连招已暂停");
         } else {
-            println!("用法: champion <英雄名称>");
+            println!("连招已恢复");
         }
-    } else if cmd == "help" {
-        println!("可用命令:");
-        println!("  champion <英雄名称> - 设置当前英雄");
-        println!("  help - 显示帮助");
-        println!("  exit/quit - 退出程序");
-    } else if cmd == "exit" || cmd == "quit" {
-        println!("退出程序...");
-        std::process::exit(0);
-    } else {
-        println!("未知命令: {}", cmd);
-        println!("输入 'help' 获取帮助");
     }
-}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("英雄联盟连招脚本 - 增强版");
-    
-    // 创建应用状态
-    let state = Arc::new(RwLock::new(AppState::new()));
-    
-    // 显示当前英雄和连招
-    {
-        let state = state.read().unwrap();
-        
-        if let Some(champion) = &state.current_champion {
-            println!("当前英雄: {}", champion);
+    // 激活或关闭层
+    fn set_layer(&mut self, layer: &str, enabled: bool) {
+        if enabled {
+            self.engine.layers.insert(layer.to_string());
+            println!("已激活层: {}", layer);
         } else {
-            println!("当前未选择英雄（使用全局脚本）");
-        }
-        
-        println!("已加载的连招:");
-        for (i, combo) in state.combos.iter().enumerate() {
-            println!("{}. {} - 触发条件: {:?}", i + 1, combo.name, combo.trigger);
+            self.engine.layers.remove(layer);
+            println!("已关闭层: {}", layer);
         }
     }
-    
-    println!("按 Ctrl+C 退出程序");
-    
-    // 创建一个通道用于优雅退出
-    let (tx, rx) = mpsc::channel();
-    
-    // 创建一个线程处理Ctrl+C信号
-    let tx_clone = tx.clone();
-    let _ = ctrlc::set_handler(move || {
-        println!("\n接收到Ctrl+C，正在退出...");
-        let _ = tx_clone.send(());
-    });
 
-    // 创建命令输入线程
-    let state_clone = Arc::clone(&state);
-    thread::spawn(move || {
-        let mut input = String::new();
-        loop {
-            input.clear();
-            print!("> ");
-            std::io::stdout().flush().unwrap();
-            
-            if std::io::stdin().read_line(&mut input).is_ok() {
-                if input.trim().is_empty() {
-                    continue;
+    // 处理输入事件，返回放行的事件；连招判定在 engine 中完成，这里执行判定的结果
+    fn handle_event(&mut self, event: &Event, device: Option<&Arc<DeviceInfo>>) -> Option<Event> {
+        let outcome = self.engine.handle_event(event, device);
+        match outcome {
+            Outcome::SwitchChampion(Some(champion)) => {
+                info!("检测到快捷键 Shift+Y，切换到{}", champion);
+                self.set_champion(champion.to_string());
+            }
+            Outcome::SwitchChampion(None) => {
+                info!("检测到快捷键 Shift+S，切换到全局配置");
+                self.clear_champion();
+            }
+            Outcome::Fired { key, .. } => {
+                for idx in self.engine.resolutions[&key].fired.clone() {
+                    self.spawn_combo(self.engine.combos[idx].clone(), Some(key), Duration::ZERO);
                 }
-                handle_command(&input, Arc::clone(&state_clone));
             }
+            Outcome::Passed | Outcome::Blocked => {}
         }
-    });
+        outcome.passes().then(|| event.clone())
+    }
 
-    // 在另一个线程中启动键盘监听
-    let keyboard_state = Arc::clone(&state);
-    thread::spawn(move || {
-        // 尝试使用 grab 模式
-        let grab_state = Arc::clone(&keyboard_state);
-        let grab_result = rdev::grab(move |event| {
-            let mut state = grab_state.write().unwrap();
-            state.handle_event(&event)
-        });
+    // 列出连接的键盘和鼠标，标出符合全局设备限制（general.devices）的设备
+    fn print_devices(&self) {
+        let Some(devices) = connected_devices() else {
+            println!("只有 Linux 下可以列出输入设备");
+            return;
+        };
+        if devices.is_empty() {
+            println!("没有找到输入设备（读取 /dev/input 可能需要 root 权限或 input 用户组）");
+            return;
+        }
         
-        if let Err(error) = grab_result {
-            // 如果grab失败，回退到listen模式
-            println!("无法使用grab模式，回退到listen模式: {:?}", error);
-            println!("注意：在listen模式下，无法屏蔽按键输入");
-            
-            // 使用另一个克隆用于listen回调
-            let listen_state = Arc::clone(&keyboard_state);
-            if let Err(error) = listen(move |event| {
-                let mut state = listen_state.write().unwrap();
-                let _ = state.handle_event(&event);
-            }) {
-                println!("无法监听键盘事件: {:?}", error);
-            }
+        let filters = &self.engine.config.general.devices;
+        println!("连接的输入设备:");
+        for device in &devices {
+            let marker = if filters.is_empty() || filters.iter().any(|filter| filter.matches(device)) {
+                "*"
+            } else {
+                " "
+            };
+            println!("  {} {}", marker, device);
         }
-    });
+        if !filters.is_empty() {
+            println!("* 表示符合全局设备限制");
+        }
+    }
+}
 
-    // 主线程等待退出信号
-    rx.recv()?;
-    
-    Ok(())
+// 命令名，用于补全
+const COMMANDS: &[&str] = &[
+    "champion", "profiles", "list", "show", "enable", "disable", "toggle", "run", "new combo",
+    "edit", "status", "pause", "resume", "layer", "why", "devices", "bind", "unbind", "source",
+    "reload", "help", "exit", "quit",
+];
+
+// 命令需要继续读取输入时使用的输入源
+trait Prompt {
+    // 输出提示并读取一行（去掉首尾空白），输入结束时返回 None
+    fn prompt(&mut self, message: &str) -> Option<String>;
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl<R: BufRead> Prompt for R {
+    fn prompt(&mut self, message: &str) -> Option<String> {
+        print!("{}", message);
+        std::io::stdout().flush().ok()?;
+        let mut line = String::new();
+        match self.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_string()),
+        }
+    }
+}
 
-    #[test]
-    fn test_key_history() {
-        let mut history = KeyHistory::new(5, 5, 1000);
-        let now = Instant::now();
-        
-        // 添加一些按键
-        history.add_action(Action {
-            key: ActionKey::Keyboard(Key::KeyQ),
-            action_type: ActionType::Press,
-            timestamp: now,
-        });
-        
-        history.add_action(Action {
-            key: ActionKey::Keyboard(Key::KeyW),
-            action_type: ActionType::Press,
-            timestamp: now,
-        });
+// 终端中的命令行：行编辑、持久化的历史记录，以及命令、英雄和连招名称的 Tab 补全
+struct LineEditor {
+    editor: Editor<CommandHelper, FileHistory>,
+    history_path: Option<PathBuf>, // 历史记录文件，没有状态目录时不保存
+}
+
+// 历史记录最多保存的命令数
+const HISTORY_SIZE: usize = 1000;
+
+impl LineEditor {
+    fn new(state: Arc<RwLock<AppState>>) -> rustyline::Result<Self> {
+        let config = rustyline::Config::builder().max_history_size(HISTORY_SIZE)?.auto_add_history(false).build();
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(CommandHelper { state }));
         
-        history.add_action(Action {
-            key: ActionKey::Keyboard(Key::KeyE),
-            action_type: ActionType::Press,
-            timestamp: now,
-        });
+        let history_path = profile::state_dir().map(|dir| dir.join("history"));
+        if let Some(path) = &history_path {
+            // 第一次运行时没有历史记录文件
+            let _ = editor.load_history(path);
+        }
         
-        // 测试按键序列
-        let recent_keys = history.get_recent_key_sequence();
-        assert_eq!(recent_keys.len(), 3);
-        assert_eq!(recent_keys[0], Key::KeyQ);
-        assert_eq!(recent_keys[1], Key::KeyW);
-        assert_eq!(recent_keys[2], Key::KeyE);
+        // 其他线程输出的日志显示在提示符上方，不打断正在输入的命令
+        let mut printer = editor.create_external_printer()?;
+        log::set_output(Some(Box::new(move |line| {
+            let _ = printer.print(line);
+        })));
+        Ok(LineEditor { editor, history_path })
+    }
+
+    // 记录命令并立即写入历史记录文件，exit 命令直接退出进程时也不会丢失
+    fn add_history(&mut self, cmd: &str) {
+        if !matches!(self.editor.add_history_entry(cmd), Ok(true)) {
+            return;
+        }
+        let Some(path) = &self.history_path else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(e) = self.editor.save_history(path) {
+            warn!("无法保存命令历史 {}: {}", path.display(), e);
+        }
+    }
+}
+
+impl Drop for LineEditor {
+    fn drop(&mut self) {
+        log::set_output(None);
+    }
+}
+
+impl Prompt for LineEditor {
+    // Ctrl+C 或 Ctrl+D 时返回 None，取消正在进行的向导
+    fn prompt(&mut self, message: &str) -> Option<String> {
+        self.editor.readline(message).ok().map(|line| line.trim().to_string())
+    }
+}
+
+// 行编辑器的补全：与 Tab+回车 的补全候选相同
+struct CommandHelper {
+    state: Arc<RwLock<AppState>>,
+}
+
+impl Completer for CommandHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        // 候选是补全后的整行，从行首开始替换
+        let completions = self.state.read().unwrap().completions(line[..pos].trim_start());
+        Ok((0, completions))
+    }
+}
+
+impl Hinter for CommandHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CommandHelper {}
+
+impl Validator for CommandHelper {}
+
+impl Helper for CommandHelper {}
+
+// 在终端中用行编辑器读取命令，Ctrl+C 或 Ctrl+D 时返回
+fn run_editor(editor: &mut LineEditor, state: &Arc<RwLock<AppState>>) {
+    loop {
+        match editor.editor.readline("> ") {
+            Ok(line) => {
+                let cmd = line.trim();
+                if cmd.is_empty() {
+                    continue;
+                }
+                editor.add_history(cmd);
+                handle_command(cmd, Arc::clone(state), editor);
+            }
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return,
+            Err(e) => {
+                error!("读取命令失败: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+// 读取一项输入：直接回车使用默认值，没有默认值时重新询问
+fn ask(input: &mut dyn Prompt, question: &str, default: Option<&str>) -> Result<String, String> {
+    let message = match default {
+        Some(default) => format!("{} [{}]: ", question, default),
+        None => format!("{}: ", question),
+    };
+    loop {
+        let answer = input.prompt(&message).ok_or("输入已结束，已取消")?;
+        if !answer.is_empty() {
+            return Ok(answer);
+        }
+        if let Some(default) = default {
+            return Ok(default.to_string());
+        }
+    }
+}
+
+// 反复询问直到输入可以被解析
+fn ask_parsed<T>(
+    input: &mut dyn Prompt,
+    question: &str,
+    default: Option<&str>,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<T, String> {
+    loop {
+        match parse(&ask(input, question, default)?) {
+            Ok(value) => return Ok(value),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+fn ask_yes_no(input: &mut dyn Prompt, question: &str, default: bool) -> Result<bool, String> {
+    let default = if default { "y" } else { "n" };
+    ask_parsed(input, &format!("{}（y/n）", question), Some(default), |answer| {
+        match answer.to_lowercase().as_str() {
+            "y" | "yes" | "是" => Ok(true),
+            "n" | "no" | "否" => Ok(false),
+            _ => Err("请输入 y 或 n".to_string()),
+        }
+    })
+}
+
+// 逐行读取连招步骤，空行结束；编辑时第一行直接回车保留当前步骤
+fn ask_steps(input: &mut dyn Prompt, current: Option<&[StepConfig]>) -> Result<Vec<StepConfig>, String> {
+    println!("输入步骤，每行一个：按键 [之前等待毫秒] [之后等待毫秒]，例如 Q 0 50 或 mouse:left，空行结束");
+    if let Some(current) = current {
+        println!("当前步骤（第一行直接回车保留）:");
+        for step in current {
+            println!("  {}", step.to_text());
+        }
+    }
+    let mut steps = Vec::new();
+    loop {
+        let line = input.prompt(&format!("步骤 {}: ", steps.len() + 1)).ok_or("输入已结束，已取消")?;
+        if line.is_empty() {
+            match current {
+                Some(current) if steps.is_empty() => return Ok(current.to_vec()),
+                _ if steps.is_empty() => println!("至少需要一个步骤"),
+                _ => return Ok(steps),
+            }
+            continue;
+        }
+        match StepConfig::parse(&line) {
+            Ok(step) => steps.push(step),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+// 创建或编辑连招的向导，query 为 None 时创建新连招
+// 询问期间不持有状态锁，以免阻塞输入事件的处理
+fn combo_wizard(state: &RwLock<AppState>, input: &mut dyn Prompt, query: Option<&str>) -> Result<(), String> {
+    let (mut champion, original) = {
+        let state = state.read().unwrap();
+        match query {
+            Some(query) => {
+                let Some(idx) = state.lookup_combo(query) else {
+                    return Ok(());
+                };
+                let name = &state.engine.combos[idx].name;
+                let (champion, combo) = state
+                    .find_combo_config(name)
+                    .ok_or_else(|| format!("配置中没有找到连招 {}", name))?;
+                (champion, Some(combo))
+            }
+            None => (state.current_champion_id(), None),
+        }
+    };
+    if original.is_none() {
+        if let Some(id) = &champion {
+            if !ask_yes_no(input, &format!("添加到英雄 {} 的连招（否则添加到全局连招）", id), true)? {
+                champion = None;
+            }
+        }
+    }
+    let current = original.as_ref();
+    
+    let name = ask(input, "名称", current.map(|combo| combo.name.as_str()))?;
+    let current_trigger = current.map(|combo| combo.trigger.to_text());
+    let trigger = ask_parsed(
+        input,
+        "触发条件（key E / seq E,R 150ms / mod ShiftLeft+Y / pattern <模式> / manual）",
+        current_trigger.as_deref(),
+        |text| match current {
+            // 保留当前触发条件时使用原值，不丢失文本表示以外的设置
+            Some(combo) if Some(text) == current_trigger.as_deref() => Ok(combo.trigger.clone()),
+            _ => TriggerType::parse(text),
+        },
+    )?;
+    let sequence = ask_steps(input, current.map(|combo| combo.sequence.as_slice()))?;
+    let block_original_input = ask_yes_no(
+        input,
+        "屏蔽原始按键",
+        current.is_some_and(|combo| combo.block_original_input),
+    )?;
+    let priority = ask_parsed(
+        input,
+        "优先级",
+        Some(&current.map_or(0, |combo| combo.priority).to_string()),
+        |answer| answer.parse().map_err(|_| format!("无效的优先级: {}", answer)),
+    )?;
+    let combo = ComboConfig {
+        name,
+        sequence,
+        trigger,
+        block_original_input,
+        active: current.is_none_or(|combo| combo.active),
+        conditions: current.map(|combo| combo.conditions.clone()).unwrap_or_default(),
+        priority,
+    };
+    
+    let old_name = current.map(|combo| combo.name.as_str());
+    let config_path = {
+        let mut state = state.write().unwrap();
+        state.apply_combo_config(champion.as_deref(), old_name, combo.clone())?;
+        state.config_path.clone()
+    };
+    println!("已应用连招: {}", combo.name);
+    
+    let Some(path) = config_path else {
+        println!("当前使用内置默认配置，没有可以保存的配置文件");
+        return Ok(());
+    };
+    if ask_yes_no(input, &format!("保存到配置文件 {}", path.display()), false)? {
+        config::save_combo(&path, champion.as_deref(), old_name, &combo)?;
+        println!("已保存到 {}", path.display());
+    }
+    Ok(())
+}
+
+// 倒计时，每秒输出一次剩余秒数，方便先切换到游戏窗口
+fn countdown(clock: &dyn Clock, name: &str, delay: Duration) {
+    if delay.is_zero() {
+        return;
+    }
+    info!("{:.1} 秒后执行连招 {}，请切换到游戏窗口", delay.as_secs_f64(), name);
+    let mut remaining = delay;
+    while !remaining.is_zero() {
+        // 先等到剩余时间为整秒，之后每次等待一秒
+        let fraction = Duration::from_nanos(remaining.subsec_nanos() as u64);
+        let step = if fraction.is_zero() { Duration::from_secs(1) } else { fraction };
+        clock.sleep(step);
+        remaining -= step;
+        if !remaining.is_zero() {
+            info!("{}...", remaining.as_secs());
+        }
+    }
+}
+
+// 解析 run 命令的参数：<连招名称> [--delay 毫秒]
+fn parse_run_args(args: &str) -> Result<(String, Duration), String> {
+    let mut name = Vec::new();
+    let mut delay = Duration::ZERO;
+    let mut words = args.split_whitespace();
+    while let Some(word) = words.next() {
+        if word == "--delay" {
+            let ms = words.next().ok_or("--delay 需要指定毫秒数")?;
+            let ms: u64 = ms.parse().map_err(|_| format!("无效的延迟: {}", ms))?;
+            delay = Duration::from_millis(ms);
+        } else {
+            name.push(word);
+        }
+    }
+    if name.is_empty() {
+        return Err("用法: run <连招名称> [--delay 毫秒]".to_string());
+    }
+    Ok((name.join(" "), delay))
+}
+
+// source 命令的最大嵌套层数，防止脚本互相引用时无限递归
+const MAX_SOURCE_DEPTH: usize = 8;
+
+thread_local! {
+    static SOURCE_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+// 逐行执行命令直到输入结束，跳过空行和 # 开头的注释
+// 非交互输入（脚本、管道）不显示提示符，改为回显执行的命令；向导会继续从同一输入读取回答
+fn run_commands<R: BufRead>(input: &mut R, state: &Arc<RwLock<AppState>>, interactive: bool) {
+    let mut line = String::new();
+    loop {
+        if interactive {
+            print!("> ");
+            let _ = std::io::stdout().flush();
+        }
+        line.clear();
+        match input.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let cmd = line.trim();
+        if cmd.is_empty() || cmd.starts_with('#') {
+            continue;
+        }
+        if !interactive {
+            println!("> {}", cmd);
+        }
+        handle_command(&line, Arc::clone(state), input);
+    }
+}
+
+// 执行脚本文件中的命令
+fn source_file(path: &Path, state: &Arc<RwLock<AppState>>) -> Result<(), String> {
+    let depth = SOURCE_DEPTH.get();
+    if depth >= MAX_SOURCE_DEPTH {
+        return Err(format!("脚本嵌套超过 {} 层，不再执行 {}", MAX_SOURCE_DEPTH, path.display()));
+    }
+    let file = File::open(path).map_err(|e| format!("无法打开脚本 {}: {}", path.display(), e))?;
+    SOURCE_DEPTH.set(depth + 1);
+    run_commands(&mut BufReader::new(file), state, false);
+    SOURCE_DEPTH.set(depth);
+    Ok(())
+}
+
+// 处理命令
+// input 用于需要继续读取输入的命令（例如创建连招的向导）
+fn handle_command(cmd: &str, state: Arc<RwLock<AppState>>, input: &mut dyn Prompt) {
+    // 终端按行输入时 Tab 会作为普通字符读入：输入开头后按 Tab 再回车，列出补全候选
+    if let Some(partial) = cmd.trim_end_matches(['\r', '\n']).strip_suffix('\t') {
+        let completions = state.read().unwrap().completions(partial.trim_start());
+        if completions.is_empty() {
+            println!("没有可用的补全");
+        }
+        for completion in completions {
+            println!("  {}", completion);
+        }
+        return;
+    }
+    let cmd = cmd.trim();
+    
+    if cmd.starts_with("champion") {
+        let parts: Vec<&str> = cmd.splitn(2, ' ').collect();
+        if parts.len() == 2 {
+            let champion_name = parts[1].to_string();
+            let mut state = state.write().unwrap();
+            state.set_champion(champion_name);
+        } else {
+            println!("用法: champion <英雄名称>");
+        }
+    } else if cmd == "pause" {
+        state.write().unwrap().set_paused(true);
+    } else if cmd == "resume" {
+        state.write().unwrap().set_paused(false);
+    } else if cmd.starts_with("layer") {
+        let parts: Vec<&str> = cmd.split_whitespace().collect();
+        match parts.as_slice() {
+            ["layer", name] | ["layer", name, "on"] => state.write().unwrap().set_layer(name, true),
+            ["layer", name, "off"] => state.write().unwrap().set_layer(name, false),
+            _ => println!("用法: layer <层名称> [on|off]"),
+        }
+    } else if cmd.starts_with("why") {
+        match cmd.split_whitespace().nth(1).map(parse_key) {
+            Some(Ok(key)) => state.read().unwrap().explain(key),
+            Some(Err(e)) => println!("{}", e),
+            None => println!("用法: why <按键>"),
+        }
+    } else if cmd == "devices" {
+        state.read().unwrap().print_devices();
+    } else if cmd == "list" {
+        state.read().unwrap().print_combos();
+    } else if cmd == "profiles" {
+        state.read().unwrap().print_profiles();
+    } else if cmd == "status" {
+        state.read().unwrap().print_status();
+    } else if cmd == "toggle" {
+        let mut state = state.write().unwrap();
+        let paused = state.engine.paused;
+        state.set_paused(!paused);
+    } else if let Some((command @ ("show" | "enable" | "disable" | "toggle"), query)) = cmd.split_once(' ') {
+        let mut state = state.write().unwrap();
+        let Some(idx) = state.lookup_combo(query.trim()) else {
+            return;
+        };
+        match command {
+            "show" => state.show_combo(idx),
+            "enable" => state.set_combo_active(idx, true),
+            "disable" => state.set_combo_active(idx, false),
+            _ => {
+                let active = !state.engine.combos[idx].active;
+                state.set_combo_active(idx, active);
+            }
+        }
+    } else if cmd == "new combo" {
+        if let Err(e) = combo_wizard(&state, input, None) {
+            println!("{}", e);
+        }
+    } else if let Some(query) = cmd.strip_prefix("edit ") {
+        if let Err(e) = combo_wizard(&state, input, Some(query.trim())) {
+            println!("{}", e);
+        }
+    } else if cmd == "run" || cmd.starts_with("run ") {
+        match parse_run_args(&cmd[3..]) {
+            Ok((name, delay)) => {
+                let state = state.read().unwrap();
+                if let Some(idx) = state.lookup_combo(&name) {
+                    state.run_combo(idx, delay);
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    } else if cmd == "reload" {
+        if let Err(e) = state.write().unwrap().reload_config() {
+            println!("{}", e);
+        }
+    } else if cmd == "bind" {
+        state.read().unwrap().print_bindings();
+    } else if let Some(args) = cmd.strip_prefix("bind ") {
+        match args.trim().split_once(char::is_whitespace) {
+            Some((key, command)) => match parse_key(key) {
+                Ok(key) => state.write().unwrap().bind_key(key, command.trim()),
+                Err(e) => println!("{}", e),
+            },
+            None => println!("用法: bind <按键> <命令>"),
+        }
+    } else if let Some(key) = cmd.strip_prefix("unbind ") {
+        match parse_key(key.trim()) {
+            Ok(key) => state.write().unwrap().unbind_key(key),
+            Err(e) => println!("{}", e),
+        }
+    } else if let Some(path) = cmd.strip_prefix("source ") {
+        if let Err(e) = source_file(Path::new(path.trim()), &state) {
+            println!("{}", e);
+        }
+    } else if matches!(cmd, "show" | "enable" | "disable" | "edit" | "unbind" | "source") {
+        let usage = match cmd {
+            "unbind" => "<按键>",
+            "source" => "<脚本文件>",
+            _ => "<连招名称>",
+        };
+        println!("用法: {} {}", cmd, usage);
+    } else if cmd == "help" {
+        println!("可用命令:");
+        println!("  champion <英雄名称> - 设置当前英雄");
+        println!("  profiles - 列出配置中的英雄");
+        println!("  list - 列出已加载的连招");
+        println!("  show <连招> - 显示连招的详细配置");
+        println!("  enable/disable <连招> - 启用/禁用连招");
+        println!("  toggle [连招] - 切换连招的启用状态，不带参数时切换暂停");
+        println!("  new combo - 逐项输入，创建新连招");
+        println!("  edit <连招> - 逐项修改连招，直接回车保留当前值");
+        println!("  run <连招> [--delay 毫秒] - 立即执行连招（包括手动触发的连招），可以先倒计时");
+        println!("  status - 显示当前英雄、暂停状态、输入模式和正在执行的连招");
+        println!("  pause/resume - 暂停/恢复连招");
+        println!("  layer <层名称> [on|off] - 激活/关闭层");
+        println!("  why <按键> - 解释该按键最近一次匹配了哪些连招");
+        println!("  devices - 列出连接的键盘和鼠标");
+        println!("  bind [按键 命令] - 绑定按键到命令，不带参数时列出绑定；unbind <按键> 解除绑定");
+        println!("  source <脚本文件> - 逐行执行文件中的命令，# 开头的行是注释");
+        println!("  reload - 重新读取配置文件");
+        println!("  help - 显示帮助");
+        println!("  连招名称支持模糊匹配；输入命令开头后按 Tab 再回车列出补全");
+        println!("  exit/quit - 退出程序");
+    } else if cmd == "exit" || cmd == "quit" {
+        println!("退出程序...");
+        std::process::exit(0);
+    } else {
+        println!("未知命令: {}", cmd);
+        println!("输入 'help' 获取帮助");
+    }
+}
+
+// 输入后端
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+enum Backend {
+    // rdev：依赖 X11，grab 失败时回退到 listen
+    #[default]
+    Rdev,
+    // evdev/uinput：直接读写 /dev/input 设备，适用于 Wayland 和控制台
+    Evdev,
+}
+
+// 捕获模式
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+enum CaptureMode {
+    // 独占输入，可以屏蔽原始按键，不可用时退出
+    Grab,
+    // 只监听输入，无法屏蔽原始按键
+    Listen,
+    // 优先独占，不可用时回退到监听
+    #[default]
+    Auto,
+}
+
+// 命令行参数，不带子命令时等同于 run
+#[derive(Debug, Parser)]
+#[command(name = "hero-rs", version, about = "英雄联盟连招脚本")]
+struct Cli {
+    #[arg(long, global = true, value_name = "PATH", help = "配置文件路径（默认查找系统配置目录和当前目录）")]
+    config: Option<PathBuf>,
+    #[arg(long, global = true, value_name = "NAME", help = "启动时使用的英雄（配置键名或显示名称）")]
+    profile: Option<String>,
+    #[arg(long, global = true, value_enum, default_value_t, help = "日志级别")]
+    log_level: Level,
+    #[arg(long, global = true, help = "演习模式：连招只记录到日志，不注入按键")]
+    dry_run: bool,
+    #[arg(long, global = true, help = "不读取标准输入的命令")]
+    no_repl: bool,
+    #[arg(long, global = true, value_enum, default_value_t, help = "输入后端")]
+    backend: Backend,
+    // evdev 后端抓取的设备，为空时自动选择所有键盘和鼠标
+    #[arg(long = "device", global = true, value_name = "PATH", help = "evdev 后端抓取的设备，可以指定多次")]
+    devices: Vec<PathBuf>,
+    #[arg(long, global = true, value_name = "PATH", help = "守护进程的控制套接字（默认 $XDG_RUNTIME_DIR/hero-rs.sock）")]
+    socket: Option<PathBuf>,
+    #[command(flatten)]
+    run: RunArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Args)]
+struct RunArgs {
+    #[arg(long, value_enum, default_value_t, help = "捕获模式")]
+    mode: CaptureMode,
+    // 监听模式下仍然加载需要屏蔽原始按键的连招
+    #[arg(long, help = "无法屏蔽原始按键时仍然加载需要屏蔽的连招")]
+    allow_unblocked: bool,
+    // 不读取标准输入，写入 pid 文件并通过控制套接字接受 hero-rs ctl 的命令
+    #[arg(long, help = "以守护进程运行，通过 hero-rs ctl 控制")]
+    daemon: bool,
+    // 只监听 127.0.0.1，端口为 0 时由系统分配
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "PORT", help = "在 127.0.0.1 的该端口上提供 HTTP 接口和编辑连招的网页")]
+    http: Option<u16>,
+    // 事件流默认只允许本机网页读取，直播叠加层等其他来源需要明确允许
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "ORIGIN", help = "允许该来源的网页读取事件流，例如 http://localhost:3000，可以重复")]
+    http_origin: Vec<String>,
+}
+
+// 子命令
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+enum Command {
+    #[command(about = "运行连招脚本（默认）")]
+    Run(RunArgs),
+    #[command(about = "校验配置文件，有错误时以非零退出码退出")]
+    Check,
+    #[command(about = "列出英雄配置和连招")]
+    List,
+    #[command(about = "立即执行一次指定的连招")]
+    Simulate {
+        #[arg(help = "连招名称")]
+        combo: String,
+    },
+    #[command(about = "把输入事件录制到文件，按 Ctrl+C 结束")]
+    Record {
+        #[arg(help = "录制文件路径")]
+        file: PathBuf,
+    },
+    #[command(about = "按原来的时间回放录制的事件，只把触发的连招输出到日志")]
+    Replay {
+        #[arg(help = "录制文件路径")]
+        file: PathBuf,
+    },
+    #[command(about = "诊断权限和运行环境")]
+    Doctor,
+    #[command(about = "向守护进程发送命令")]
+    Ctl {
+        #[command(subcommand)]
+        command: CtlCommand,
+    },
+}
+
+// ctl 的命令，对应守护进程控制接口的方法
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+enum CtlCommand {
+    #[command(about = "切换英雄，不带参数时切换到全局连招")]
+    Champion {
+        #[arg(help = "英雄名称")]
+        name: Vec<String>,
+    },
+    #[command(about = "列出配置中的英雄")]
+    Profiles,
+    #[command(about = "列出已加载的连招")]
+    List,
+    #[command(about = "启用连招")]
+    Enable {
+        #[arg(required = true, help = "连招名称，支持模糊匹配")]
+        combo: Vec<String>,
+    },
+    #[command(about = "禁用连招")]
+    Disable {
+        #[arg(required = true, help = "连招名称，支持模糊匹配")]
+        combo: Vec<String>,
+    },
+    #[command(about = "立即执行连招")]
+    Run {
+        #[arg(required = true, help = "连招名称，支持模糊匹配")]
+        combo: Vec<String>,
+        #[arg(long, value_name = "MS", default_value_t = 0, help = "执行前等待的毫秒数")]
+        delay: u64,
+    },
+    #[command(about = "暂停连招")]
+    Pause,
+    #[command(about = "恢复连招")]
+    Resume,
+    #[command(about = "重新读取配置文件")]
+    Reload,
+    #[command(about = "显示运行状态")]
+    Status,
+    #[command(about = "显示运行统计：处理的事件数、屏蔽的按键数和每个连招的执行次数")]
+    Stats,
+    #[command(about = "持续输出守护进程发布的事件，每行一个 JSON")]
+    Events,
+    #[command(about = "退出守护进程")]
+    Stop,
+}
+
+#[cfg(unix)]
+impl CtlCommand {
+    // 对应的方法名和参数
+    fn request(&self) -> (&'static str, Value) {
+        match self {
+            CtlCommand::Champion { name } if name.is_empty() => ("set_champion", json!({ "name": null })),
+            CtlCommand::Champion { name } => ("set_champion", json!({ "name": name.join(" ") })),
+            CtlCommand::Profiles => ("profiles", Value::Null),
+            CtlCommand::List => ("list_combos", Value::Null),
+            CtlCommand::Enable { combo } => ("enable", json!({ "combo": combo.join(" ") })),
+            CtlCommand::Disable { combo } => ("disable", json!({ "combo": combo.join(" ") })),
+            CtlCommand::Run { combo, delay } => ("run_combo", json!({ "combo": combo.join(" "), "delay_ms": delay })),
+            CtlCommand::Pause => ("pause", Value::Null),
+            CtlCommand::Resume => ("resume", Value::Null),
+            CtlCommand::Reload => ("reload", Value::Null),
+            CtlCommand::Status => ("status", Value::Null),
+            CtlCommand::Stats => ("stats", Value::Null),
+            CtlCommand::Events => ("subscribe", Value::Null),
+            CtlCommand::Stop => ("stop", Value::Null),
+        }
+    }
+}
+
+impl Cli {
+    // clap 无法表达的参数约束
+    fn validate(&self) -> Result<(), String> {
+        if !self.devices.is_empty() && self.backend != Backend::Evdev {
+            return Err("--device 只能与 --backend evdev 一起使用".to_string());
+        }
+        // run 的参数只能在省略子命令时放在最前面，否则应写在 run 之后
+        if self.command.is_some() && self.run != RunArgs::default() {
+            return Err("--mode、--allow-unblocked、--daemon、--http 和 --http-origin 只能用于 run 子命令".to_string());
+        }
+        Ok(())
+    }
+
+    fn command_or_run(&self) -> Command {
+        self.command.clone().unwrap_or_else(|| Command::Run(self.run.clone()))
+    }
+}
+
+// 当前连接的键盘和鼠标，不支持的平台返回 None
+#[cfg(target_os = "linux")]
+fn connected_devices() -> Option<Vec<DeviceInfo>> {
+    Some(hero_rs::input::evdev::list_devices())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn connected_devices() -> Option<Vec<DeviceInfo>> {
+    None
+}
+
+// 按捕获模式依次尝试的输入源，以及与之配套的输出端
+struct InputBackend {
+    sources: Vec<Box<dyn InputSource>>,
+    sink: Arc<dyn InputSink>,
+}
+
+fn open_backend(backend: Backend, devices: &[PathBuf], mode: CaptureMode) -> Result<InputBackend, InputError> {
+    match backend {
+        Backend::Rdev => {
+            let sources: Vec<Box<dyn InputSource>> = match mode {
+                CaptureMode::Grab => vec![Box::new(RdevGrab)],
+                CaptureMode::Listen => vec![Box::new(RdevListen)],
+                CaptureMode::Auto => vec![Box::new(RdevGrab), Box::new(RdevListen)],
+            };
+            Ok(InputBackend { sources, sink: Arc::new(RdevSink) })
+        }
+        Backend::Evdev => open_evdev(devices, mode),
+    }
+}
+
+// 打开 evdev 后端，独占和监听两种输入源与输出端共用同一个虚拟设备
+#[cfg(target_os = "linux")]
+fn open_evdev(devices: &[PathBuf], mode: CaptureMode) -> Result<InputBackend, InputError> {
+    let grab = EvdevGrab::open(devices)?;
+    println!("evdev 后端已打开以下设备:");
+    for device in grab.devices() {
+        println!("  {}", device);
+    }
+    let sink = Arc::new(grab.sink());
+    let sources: Vec<Box<dyn InputSource>> = match mode {
+        CaptureMode::Grab => vec![Box::new(grab)],
+        CaptureMode::Listen => vec![Box::new(grab.listener()?)],
+        CaptureMode::Auto => {
+            let listener = grab.listener()?;
+            vec![Box::new(grab), Box::new(listener)]
+        }
+    };
+    Ok(InputBackend { sources, sink })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_evdev(_devices: &[PathBuf], _mode: CaptureMode) -> Result<InputBackend, InputError> {
+    Err(InputError::Device(
+        "evdev 后端只支持 Linux".to_string(),
+        std::io::ErrorKind::Unsupported.into(),
+    ))
+}
+
+// 输出输入模式的能力
+fn print_capabilities(source: &dyn InputSource, sink: &dyn InputSink) {
+    let yes_no = |value| if value { "是" } else { "否" };
+    println!("输入模式: {}", source.name());
+    println!("  屏蔽原始按键: {}", yes_no(source.can_block()));
+    println!("  模拟按键: {}", yes_no(sink.can_inject()));
+}
+
+// 从输入源读取事件并交给应用状态处理，阻塞直到输入源结束
+fn run_input(source: &mut dyn InputSource, state: Arc<RwLock<AppState>>) -> Result<(), InputError> {
+    source.run(Box::new(move |event, device| {
+        let mut state = state.write().unwrap();
+        state.handle_event(&event, device)
+    }))
+}
+
+// 加载配置文件：优先使用 --config 指定的路径
+fn load_config(path: Option<&Path>) -> Result<Config, String> {
+    match path.map(Path::to_path_buf).or_else(Config::find) {
+        Some(path) => {
+            let config = Config::load(&path)?;
+            info!("已加载配置文件: {}", path.display());
+            Ok(config)
+        }
+        None => {
+            info!("未找到配置文件，使用内置默认配置");
+            Ok(Config::builtin())
+        }
+    }
+}
+
+// 启动时使用的英雄：命令行的 --profile 优先，然后是上次使用的英雄，最后是配置中的 current_champion
+// 上次使用的英雄已经不在配置中时忽略它
+fn startup_profile(cli_profile: Option<&str>, last_profile: Option<String>, config: &Config) -> Option<String> {
+    if let Some(profile) = cli_profile {
+        return Some(profile.to_string());
+    }
+    if let Some(profile) = last_profile {
+        if config.find_champion(&profile).is_some() {
+            info!("恢复上次使用的英雄: {}", profile);
+            return Some(profile);
+        }
+        warn!("上次使用的英雄 {} 不在配置中，已忽略", profile);
+    }
+    config.current_champion.clone()
+}
+
+// 切换到指定的英雄
+fn select_profile(state: &mut AppState, profile: Option<&str>) -> Result<(), String> {
+    if let Some(profile) = profile {
+        if state.engine.config.find_champion(profile).is_none() {
+            return Err(format!("配置中没有英雄 {}（运行 hero-rs list 查看所有英雄）", profile));
+        }
+        state.set_champion(profile.to_string());
+    }
+    Ok(())
+}
+
+// 按名称查找连招（不区分大小写），先查全局连招再查英雄连招
+fn find_combo<'a>(config: &'a Config, name: &str) -> Option<&'a ComboConfig> {
+    let name = name.to_lowercase();
+    config
+        .global_combos
+        .iter()
+        .chain(config.champion_specific.values().flat_map(|champion| &champion.combos))
+        .find(|combo| combo.name.to_lowercase() == name)
+}
+
+// 网页编辑连招使用的表单：触发条件和步骤使用与命令行向导相同的文本写法
+#[cfg(feature = "http")]
+fn combo_form(champion: Option<&str>, combo: &ComboConfig) -> Value {
+    json!({
+        "champion": champion,
+        "name": combo.name,
+        "trigger": combo.trigger.to_text(),
+        "steps": combo.sequence.iter().map(StepConfig::to_text).collect::<Vec<_>>(),
+        "block_original_input": combo.block_original_input,
+        "priority": combo.priority,
+    })
+}
+
+// 把表单中给出的字段应用到连招上，没有给出的字段保持不变
+#[cfg(feature = "http")]
+fn apply_combo_form(mut combo: ComboConfig, form: &Value) -> Result<ComboConfig, String> {
+    let field = |name| form.get(name).filter(|value| !value.is_null());
+    if let Some(value) = field("name") {
+        combo.name = value.as_str().ok_or("name 必须是字符串")?.trim().to_string();
+    }
+    if let Some(value) = field("trigger") {
+        let text = value.as_str().ok_or("trigger 必须是字符串")?.trim();
+        // 与当前写法相同时保留原值，不丢失文本表示以外的设置
+        if text != combo.trigger.to_text() {
+            combo.trigger = TriggerType::parse(text)?;
+        }
+    }
+    if let Some(value) = field("steps") {
+        let steps = value.as_array().ok_or("steps 必须是字符串数组")?;
+        combo.sequence = steps
+            .iter()
+            .map(|step| step.as_str().ok_or_else(|| "steps 必须是字符串数组".to_string()).and_then(StepConfig::parse))
+            .collect::<Result<_, _>>()?;
+    }
+    if let Some(value) = field("block_original_input") {
+        combo.block_original_input = value.as_bool().ok_or("block_original_input 必须是布尔值")?;
+    }
+    if let Some(value) = field("priority") {
+        combo.priority = value.as_i64().and_then(|priority| i32::try_from(priority).ok()).ok_or("priority 必须是整数")?;
+    }
+    Ok(combo)
+}
+
+// 新建（query 为 None）或修改连招，与命令行向导一样检查后立即生效，请求中 save 为 true 时保存到配置文件
+#[cfg(feature = "http")]
+fn save_combo_form(state: &RwLock<AppState>, query: Option<&str>, form: &Value) -> http::Response {
+    use http::Response;
+    let (champion, original) = {
+        let state = state.read().unwrap();
+        match query {
+            Some(query) => {
+                let found = state
+                    .find_combo_index(query)
+                    .and_then(|idx| state.find_combo_config(&state.engine.combos[idx].name).ok_or(format!("配置中没有找到连招 {}", query)));
+                match found {
+                    Ok((champion, combo)) => (champion, Some(combo)),
+                    Err(e) => return Response::error(404, e),
+                }
+            }
+            None => match form.get("champion").filter(|value| !value.is_null()) {
+                None => (None, None),
+                Some(Value::String(id)) => (Some(id.clone()), None),
+                Some(_) => return Response::error(400, "champion 必须是英雄的配置键名或 null"),
+            },
+        }
+    };
+    
+    let base = match &original {
+        Some(combo) => combo.clone(),
+        None if form.get("trigger").is_none_or(Value::is_null) => return Response::error(400, "新建连招需要 trigger"),
+        None => ComboConfig {
+            name: String::new(),
+            sequence: Vec::new(),
+            trigger: TriggerType::Manual,
+            block_original_input: false,
+            active: true,
+            conditions: config::Conditions::default(),
+            priority: 0,
+        },
+    };
+    let combo = match apply_combo_form(base, form) {
+        Ok(combo) => combo,
+        Err(e) => return Response::error(400, e),
+    };
+    
+    let old_name = original.as_ref().map(|combo| combo.name.as_str());
+    let config_path = {
+        let mut state = state.write().unwrap();
+        if let Err(e) = state.apply_combo_config(champion.as_deref(), old_name, combo.clone()) {
+            return Response::error(422, e);
+        }
+        state.config_path.clone()
+    };
+    println!("已应用连招: {}", combo.name);
+    
+    let save = form.get("save").and_then(Value::as_bool).unwrap_or(false);
+    if save {
+        let Some(path) = config_path else {
+            return Response::error(409, "连招已应用，但当前使用内置默认配置，没有可以保存的配置文件");
+        };
+        if let Err(e) = config::save_combo(&path, champion.as_deref(), old_name, &combo) {
+            return Response::error(500, format!("连招已应用，但保存失败: {}", e));
+        }
+        println!("已保存到 {}", path.display());
+    }
+    let status = if original.is_some() { 200 } else { 201 };
+    Response::json(status, json!({ "combo": combo_form(champion.as_deref(), &combo), "saved": save }))
+}
+
+// 处理 HTTP 请求：GET / 返回网页，/api 下是 JSON 接口
+#[cfg(feature = "http")]
+fn handle_http(state: &RwLock<AppState>, request: &http::Request) -> http::Response {
+    use http::Response;
+    let body = &request.body;
+    match (request.method.as_str(), request.segments().as_slice()) {
+        ("GET", []) => Response::html(http::INDEX_HTML),
+        ("GET", ["api", "status"]) => Response::ok(state.read().unwrap().status()),
+        ("GET", ["api", "stats"]) => Response::ok(state.read().unwrap().stats()),
+        ("GET", ["api", "profiles"]) => Response::ok(state.read().unwrap().profile_list()),
+        ("GET", ["api", "events"]) => {
+            let input = request.param("input") == Some("true");
+            Response::events(state.read().unwrap().engine.events.subscribe(), input)
+        }
+        ("PUT", ["api", "profile"]) => {
+            let mut state = state.write().unwrap();
+            let result = match body.get("name").and_then(Value::as_str) {
+                Some(name) => select_profile(&mut state, Some(name)),
+                None => {
+                    state.clear_champion();
+                    Ok(())
+                }
+            };
+            match result {
+                Ok(()) => Response::ok(json!({ "champion": state.engine.current_champion })),
+                Err(e) => Response::error(404, e),
+            }
+        }
+        ("GET", ["api", "combos"]) => Response::ok(state.read().unwrap().combo_list()),
+        ("POST", ["api", "combos"]) => save_combo_form(state, None, body),
+        ("GET", ["api", "combos", query]) => {
+            let state = state.read().unwrap();
+            let found = state
+                .find_combo_index(query)
+                .and_then(|idx| state.find_combo_config(&state.engine.combos[idx].name).ok_or(format!("配置中没有找到连招 {}", query)));
+            match found {
+                Ok((champion, combo)) => Response::ok(combo_form(champion.as_deref(), &combo)),
+                Err(e) => Response::error(404, e),
+            }
+        }
+        ("PUT", ["api", "combos", query]) => save_combo_form(state, Some(query), body),
+        ("POST", ["api", "combos", query, action @ ("enable" | "disable" | "run")]) => {
+            let mut state = state.write().unwrap();
+            let idx = match state.find_combo_index(query) {
+                Ok(idx) => idx,
+                Err(e) => return Response::error(404, e),
+            };
+            match *action {
+                "run" => state.run_combo(idx, Duration::ZERO),
+                action => state.set_combo_active(idx, action == "enable"),
+            }
+            let combo = &state.engine.combos[idx];
+            Response::ok(json!({ "combo": combo.name, "active": combo.active }))
+        }
+        (_, ["api", ..]) => Response::error(404, "没有这个接口"),
+        _ => Response::error(404, "没有这个页面"),
+    }
+}
+
+// 控制套接字路径：--socket 优先
+#[cfg(unix)]
+fn socket_path(cli: &Cli) -> Result<PathBuf, String> {
+    cli.socket.clone().or_else(default_socket_path).ok_or_else(|| "无法确定控制套接字的位置，请用 --socket 指定".to_string())
+}
+
+#[cfg(unix)]
+fn default_socket_path() -> Option<PathBuf> {
+    ipc::default_socket_path()
+}
+
+// 字符串参数，缺少时返回参数错误
+#[cfg(unix)]
+fn str_param<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new(ipc::INVALID_PARAMS, format!("缺少字符串参数 {}", name)))
+}
+
+// 处理控制接口的方法调用，与命令行中对应的命令相同（stop 由守护进程处理）
+#[cfg(unix)]
+fn handle_rpc(state: &RwLock<AppState>, method: &str, params: &Value) -> Result<Value, RpcError> {
+    let failed = |e: String| RpcError::new(ipc::COMMAND_FAILED, e);
+    match method {
+        "status" => Ok(state.read().unwrap().status()),
+        "list_combos" => Ok(state.read().unwrap().combo_list()),
+        "stats" => Ok(state.read().unwrap().stats()),
+        "profiles" => Ok(state.read().unwrap().profile_list()),
+        "set_champion" => {
+            let mut state = state.write().unwrap();
+            match params.get("name").and_then(Value::as_str) {
+                Some(name) => select_profile(&mut state, Some(name)).map_err(failed)?,
+                None => state.clear_champion(),
+            }
+            Ok(json!({ "champion": state.engine.current_champion }))
+        }
+        "enable" | "disable" => {
+            let query = str_param(params, "combo")?;
+            let mut state = state.write().unwrap();
+            let idx = state.find_combo_index(query).map_err(failed)?;
+            state.set_combo_active(idx, method == "enable");
+            let combo = &state.engine.combos[idx];
+            Ok(json!({ "combo": combo.name, "active": combo.active }))
+        }
+        "run_combo" => {
+            let query = str_param(params, "combo")?;
+            let delay = match params.get("delay_ms") {
+                None | Some(Value::Null) => 0,
+                Some(value) => value
+                    .as_u64()
+                    .ok_or_else(|| RpcError::new(ipc::INVALID_PARAMS, "delay_ms 必须是非负整数"))?,
+            };
+            let state = state.read().unwrap();
+            let idx = state.find_combo_index(query).map_err(failed)?;
+            state.run_combo(idx, Duration::from_millis(delay));
+            Ok(json!({ "combo": state.engine.combos[idx].name }))
+        }
+        "pause" | "resume" => {
+            let paused = method == "pause";
+            state.write().unwrap().set_paused(paused);
+            Ok(json!({ "paused": paused }))
+        }
+        "reload" => {
+            let combos = state.write().unwrap().reload_config().map_err(failed)?;
+            Ok(json!({ "combos": combos }))
+        }
+        _ => Err(RpcError::new(ipc::METHOD_NOT_FOUND, format!("未知方法: {}", method))),
+    }
+}
+
+// 守护进程：在控制套接字上接受命令，stop 时退出
+#[cfg(unix)]
+fn serve_control(listener: std::os::unix::net::UnixListener, state: Arc<RwLock<AppState>>, tx: mpsc::Sender<i32>) {
+    let tx = Mutex::new(tx);
+    let events = Arc::clone(&state.read().unwrap().engine.events);
+    ipc::serve(
+        listener,
+        Arc::new(move |method: &str, params: Value| {
+            if method == "stop" {
+                println!("收到 stop 命令，正在退出...");
+                let _ = tx.lock().unwrap().send(0);
+                return Ok(Value::Null);
+            }
+            handle_rpc(&state, method, &params)
+        }),
+        events,
+    );
+}
+
+// 向守护进程发送命令，输出 JSON 格式的结果
+#[cfg(unix)]
+fn ctl(cli: &Cli, command: &CtlCommand) -> Result<(), String> {
+    if let CtlCommand::Events = command {
+        return ipc::subscribe(&socket_path(cli)?, |event| println!("{}", event));
+    }
+    let (method, params) = command.request();
+    let result = ipc::call(&socket_path(cli)?, method, params)?;
+    if !result.is_null() {
+        println!("{}", serde_json::to_string_pretty(&result).map_err(|e| e.to_string())?);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn ctl(_cli: &Cli, _command: &CtlCommand) -> Result<(), String> {
+    Err("守护进程只支持 Unix 系统".to_string())
+}
+
+// 运行连招脚本
+fn run(cli: &Cli, args: &RunArgs) -> Result<(), Box<dyn std::error::Error>> {
+    println!("英雄联盟连招脚本 - 增强版");
+    let config_path = cli.config.clone().or_else(Config::find);
+    let config = load_config(config_path.as_deref())?;
+    
+    // 守护进程先获取单实例锁，避免第二个实例抓取输入设备
+    #[cfg(not(unix))]
+    if args.daemon {
+        return Err("守护进程只支持 Unix 系统".into());
+    }
+    #[cfg(unix)]
+    let daemon = match args.daemon {
+        true => {
+            let path = socket_path(cli)?;
+            let daemon = ipc::Instance::acquire(&path)?;
+            println!("守护进程 pid {}，控制套接字 {}", std::process::id(), path.display());
+            Some(daemon)
+        }
+        false => None,
+    };
+    
+    // 打开输入后端，演习模式下用只记录日志的输出端代替
+    let InputBackend { sources, mut sink } = open_backend(cli.backend, &cli.devices, args.mode)?;
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    if cli.dry_run {
+        sink = Arc::new(DryRunSink::new(Arc::clone(&clock)));
+    }
+    
+    // 创建应用状态
+    let mut state = AppState::new(config, Arc::clone(&sink), clock);
+    state.allow_unblocked = args.allow_unblocked;
+    state.engine.dry_run = cli.dry_run;
+    state.config_path = config_path;
+    // 在选择英雄之前启用钩子，启动时选择英雄也会执行 champion_switched 钩子
+    state.hooks = Some(Hooks::start(&state.engine.config, &state.engine.events));
+    let profile_store = ProfileStore::new();
+    let last_profile = profile_store.as_ref().and_then(ProfileStore::load);
+    let profile = startup_profile(cli.profile.as_deref(), last_profile, &state.engine.config);
+    state.profile_store = profile_store;
+    select_profile(&mut state, profile.as_deref())?;
+    
+    // 按下绑定的按键时，输入线程把命令发到这里执行，避免在处理按键时等待状态锁
+    let (command_tx, command_rx) = mpsc::channel::<String>();
+    state.engine.command_tx = Some(command_tx);
+    let on_start = state.engine.config.on_start.clone();
+    let state = Arc::new(RwLock::new(state));
+    let command_state = Arc::clone(&state);
+    thread::spawn(move || {
+        for command in command_rx {
+            handle_command(&command, Arc::clone(&command_state), &mut std::io::empty());
+        }
+    });
+    
+    // 本机 HTTP 接口和网页
+    #[cfg(feature = "http")]
+    if let Some(port) = args.http {
+        let http_state = Arc::clone(&state);
+        let handler = Arc::new(move |request: &http::Request| handle_http(&http_state, request));
+        let addr = http::serve(port, args.http_origin.clone(), handler)?;
+        println!("网页和 HTTP 接口: http://{}/", addr);
+    }
+    
+    // 执行配置中的启动命令
+    for command in &on_start {
+        println!("> {}", command);
+        handle_command(command, Arc::clone(&state), &mut std::io::empty());
+    }
+    
+    // 显示当前英雄和连招
+    {
+        let state = state.read().unwrap();
+        
+        if let Some(champion) = &state.engine.current_champion {
+            println!("当前英雄: {}", champion);
+        } else {
+            println!("当前未选择英雄（使用全局脚本）");
+        }
+        
+        println!("已加载的连招:");
+        for (i, combo) in state.engine.combos.iter().enumerate() {
+            println!("{}. {} - 触发条件: {:?}", i + 1, combo.name, combo.trigger);
+        }
+    }
+    
+    println!("按 Ctrl+C 退出程序");
+    
+    // 创建一个通道用于优雅退出，传递退出码
+    let (tx, rx) = mpsc::channel();
+    
+    // 创建一个线程处理Ctrl+C信号
+    let tx_clone = tx.clone();
+    let _ = ctrlc::set_handler(move || {
+        println!("\n接收到Ctrl+C，正在退出...");
+        let _ = tx_clone.send(0);
+    });
+
+    #[cfg(unix)]
+    let instance = daemon.map(|(instance, listener)| {
+        serve_control(listener, Arc::clone(&state), tx.clone());
+        instance
+    });
+
+    // 创建命令输入线程（由启动器托管时用 --no-repl 关闭，守护进程不读取标准输入）
+    // 终端中使用行编辑器，命令行处于原始模式，Ctrl+C 由编辑器读到后退出程序；
+    // 标准输入不是终端时（启动器通过管道传入命令）不显示提示符，输入结束后继续运行
+    if !cli.no_repl && !args.daemon {
+        let state_clone = Arc::clone(&state);
+        let tx_repl = tx.clone();
+        thread::spawn(move || {
+            let stdin = std::io::stdin();
+            let interactive = stdin.is_terminal();
+            if interactive {
+                match LineEditor::new(Arc::clone(&state_clone)) {
+                    Ok(mut editor) => {
+                        run_editor(&mut editor, &state_clone);
+                        drop(editor);
+                        println!("正在退出...");
+                        let _ = tx_repl.send(0);
+                        return;
+                    }
+                    Err(e) => warn!("无法启用行编辑，使用普通输入: {}", e),
+                }
+            }
+            run_commands(&mut stdin.lock(), &state_clone, interactive);
+        });
+    }
+
+    // 在另一个线程中启动键盘监听
+    let keyboard_state = Arc::clone(&state);
+    let dry_run = cli.dry_run;
+    thread::spawn(move || {
+        // 依次尝试捕获模式允许的输入源，全部不可用时以非零退出码退出
+        let mut sources = sources.into_iter().peekable();
+        while let Some(mut source) = sources.next() {
+            {
+                let mut state = keyboard_state.write().unwrap();
+                state.input_mode = Some(source.name());
+                state.set_can_block(source.can_block());
+            }
+            print_capabilities(source.as_ref(), sink.as_ref());
+            if dry_run {
+                println!("  演习模式: 连招只记录到日志，不会注入按键或屏蔽原始按键");
+            }
+            
+            let Err(error) = run_input(source.as_mut(), Arc::clone(&keyboard_state)) else {
+                println!("输入源 {} 已停止", source.name());
+                return;
+            };
+            match sources.peek() {
+                Some(next) => {
+                    warn!("无法使用 {}，回退到 {}: {}", source.name(), next.name(), error);
+                    if !next.can_block() {
+                        println!("注意：在listen模式下，无法屏蔽按键输入");
+                    }
+                }
+                None => {
+                    error!("{}", error);
+                    println!("请求的输入模式不可用，运行 hero-rs doctor 诊断权限和运行环境");
+                    let _ = tx.send(1);
+                    return;
+                }
+            }
+        }
+    });
+
+    // 主线程等待退出信号
+    let code = rx.recv()?;
+    #[cfg(unix)]
+    drop(instance);
+    if code != 0 {
+        std::process::exit(code);
+    }
+    
+    Ok(())
+}
+
+// 校验配置文件
+fn check(cli: &Cli) -> Result<(), String> {
+    let config = load_config(cli.config.as_deref())?;
+    for profile in [&cli.profile, &config.current_champion].into_iter().flatten() {
+        if config.find_champion(profile).is_none() {
+            return Err(format!("配置中没有英雄 {}", profile));
+        }
+    }
+    let champion_combos: usize = config.champion_specific.values().map(|champion| champion.combos.len()).sum();
+    println!(
+        "配置有效: {} 个全局连招，{} 个英雄的 {} 个连招",
+        config.global_combos.len(),
+        config.champion_specific.len(),
+        champion_combos
+    );
+    Ok(())
+}
+
+// 列出英雄配置和连招
+fn list(cli: &Cli) -> Result<(), String> {
+    let config = load_config(cli.config.as_deref())?;
+    let print_combo = |combo: &ComboConfig| {
+        let status = if combo.active { "" } else { "（已禁用）" };
+        println!("  {}{} - 触发条件: {:?}", combo.name, status, combo.trigger);
+    };
+    
+    println!("全局连招:");
+    config.global_combos.iter().for_each(print_combo);
+    
+    let mut champions: Vec<_> = config.champion_specific.iter().collect();
+    champions.sort_by_key(|(id, _)| id.as_str());
+    for (id, champion) in champions {
+        println!("英雄 {}（{}）:", id, champion.name);
+        champion.combos.iter().for_each(print_combo);
+    }
+    Ok(())
+}
+
+// 立即执行一次指定的连招
+fn simulate(cli: &Cli, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config(cli.config.as_deref())?;
+    let Some(combo) = find_combo(&config, name) else {
+        return Err(format!("没有名为 {} 的连招（运行 hero-rs list 查看所有连招）", name).into());
+    };
+    let combo = Combo::from_config(combo, config.general.default_delay_ms);
+    
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let sink = if cli.dry_run {
+        Arc::new(DryRunSink::new(Arc::clone(&clock)))
+    } else {
+        let sink = open_backend(cli.backend, &cli.devices, CaptureMode::Listen)?.sink;
+        if cli.backend == Backend::Evdev {
+            // 新建的 uinput 虚拟设备需要一点时间被系统识别，否则最开始的按键会丢失
+            clock.sleep(Duration::from_millis(200));
+        }
+        sink
+    };
+    // 只执行一次，等日志线程写完再退出
+    let events = EventBus::new();
+    let logger = spawn_event_logger(&events);
+    events.publish(AppEvent::ComboStarted { combo: combo.name.clone() });
+    let result = combo.execute(sink.as_ref(), clock.as_ref(), &events);
+    drop(events);
+    if let Some(logger) = logger {
+        let _ = logger.join();
+    }
+    result?;
+    Ok(())
+}
+
+// 把输入事件录制到文件，直到按 Ctrl+C
+fn record(cli: &Cli, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let InputBackend { sources, .. } = open_backend(cli.backend, &cli.devices, CaptureMode::Listen)?;
+    let file = File::create(path).map_err(|e| format!("无法创建录制文件 {}: {}", path.display(), e))?;
+    let recorder = Mutex::new(Recorder::new(BufWriter::new(file)));
+    
+    println!("正在录制输入事件到 {}，按 Ctrl+C 结束", path.display());
+    let mut source = sources.into_iter().next().expect("监听模式总有一个输入源");
+    source.run(Box::new(move |event, _| {
+        debug!("{}", describe(&event.event_type));
+        if let Err(e) = recorder.lock().unwrap().record(&event) {
+            error!("写入录制文件失败: {}", e);
+        }
+        Some(event)
+    }))?;
+    Ok(())
+}
+
+// 按录制时的时间间隔回放事件，连招始终只输出到日志
+fn replay(cli: &Cli, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let events = record::load(path)?;
+    let config = load_config(cli.config.as_deref())?;
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let sink = Arc::new(DryRunSink::new(Arc::clone(&clock)));
+    let mut state = AppState::new(config, sink, Arc::clone(&clock));
+    state.engine.dry_run = true;
+    select_profile(&mut state, cli.profile.as_deref())?;
+    
+    println!("回放 {} 个事件（演习模式）", events.len());
+    let start = clock.now();
+    for recorded in &events {
+        let at = start + Duration::from_millis(recorded.offset_ms);
+        clock.sleep(at.saturating_duration_since(clock.now()));
+        state.handle_event(&clock_event(clock.as_ref(), recorded.event), None);
+    }
+    
+    // 等待最后触发的连招执行完毕
+    let longest = state.engine.combos.iter().map(Combo::duration).max().unwrap_or_default();
+    clock.sleep(longest);
+    println!("回放结束");
+    Ok(())
+}
+
+// 以时钟的当前时间构造输入事件
+fn clock_event(clock: &dyn Clock, event_type: EventType) -> Event {
+    event_at(event_type, clock.system_now())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    if let Err(message) = cli.validate() {
+        Cli::command().error(ErrorKind::ArgumentConflict, message).exit();
+    }
+    log::set_level(cli.log_level);
+    
+    match cli.command_or_run() {
+        Command::Run(args) => run(&cli, &args),
+        Command::Check => {
+            if let Err(e) = check(&cli) {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::List => Ok(list(&cli)?),
+        Command::Simulate { combo } => simulate(&cli, &combo),
+        Command::Record { file } => record(&cli, &file),
+        Command::Replay { file } => replay(&cli, &file),
+        Command::Doctor => {
+            let checks = doctor::run_checks();
+            if !doctor::print_report(&checks) {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Ctl { command } => {
+            if let Err(e) = ctl(&cli, &command) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hero_rs::{
+        clock::{self, ManualClock},
+        config::{ActionKey, ChampionConfig, Conditions},
+        engine::{Action, ActionType, Resolution},
+        input::{event, MockSink, MockSource},
+    };
+    use rdev::Button;
+    use std::time::Instant;
+
+    #[test]
+    fn test_sample_config_parses() {
+        let config = Config::parse(include_str!("../config.toml")).unwrap();
+        assert!(!config.global_combos.is_empty());
+        assert!(config.find_champion("亚索").is_some());
+    }
+
+    #[test]
+    fn test_combo_conditions() {
+        let config = Config::parse(
+            r#"
+            [[global_combos]]
+            name = "右键时Q"
+            sequence = [{ key = { Keyboard = "KeyQ" } }]
+            trigger = { type = "SingleKey", key = "KeyA" }
+            conditions = { held = [{ Mouse = "Right" }], not_held = [{ Keyboard = "ShiftLeft" }], champions = ["Yasuo"] }
+            "#,
+        )
+        .unwrap();
+        let mut state = AppState::new(config, Arc::new(MockSink::new()), Arc::new(SystemClock));
+        let conditions = state.engine.combos[0].conditions.clone();
+        let press = |key: ActionKey, action_type: ActionType| Action {
+            key,
+            action_type,
+            timestamp: Instant::now(),
+            device: None,
+        };
+
+        // 未选择英雄、未按住右键
+        assert!(state.engine.check_conditions(&conditions).is_err());
+
+        state.engine.current_champion = Some("yasuo".to_string());
+        assert!(state.engine.check_conditions(&conditions).is_err());
+
+        state.engine.history.add_action(press(ActionKey::Mouse(Button::Right), ActionType::Press));
+        assert!(state.engine.check_conditions(&conditions).is_ok());
+
+        // 按住Shift时不触发
+        state.engine.history.add_action(press(ActionKey::Keyboard(Key::ShiftLeft), ActionType::Press));
+        assert!(state.engine.check_conditions(&conditions).is_err());
+        state.engine.history.add_action(press(ActionKey::Keyboard(Key::ShiftLeft), ActionType::Release));
+        assert!(state.engine.check_conditions(&conditions).is_ok());
+
+        // 暂停和聊天时不触发
+        state.engine.paused = true;
+        assert!(state.engine.check_conditions(&conditions).is_err());
+        state.engine.paused = false;
+        state.engine.chat_open = true;
+        assert!(state.engine.check_conditions(&conditions).is_err());
+    }
+
+    #[test]
+    fn test_end_to_end_with_mock_backend() {
+        let config = Config::parse(
+            r#"
+            general = { default_delay_ms = 0 }
+            [[global_combos]]
+            name = "Tab触发A+左键"
+            sequence = [{ key = { Keyboard = "KeyA" } }, { key = { Mouse = "Left" } }]
+            trigger = { type = "SingleKey", key = "Tab" }
+            [[global_combos]]
+            name = "E+R触发Q"
+            sequence = [{ key = { Keyboard = "KeyQ" } }]
+            trigger = { type = "KeySequence", keys = ["KeyE", "KeyR"], timeout_ms = 150 }
+            block_original_input = true
+            "#,
+        )
+        .unwrap();
+        let sink = MockSink::new();
+        let state = Arc::new(RwLock::new(AppState::new(config, Arc::new(sink.clone()), Arc::new(SystemClock))));
+
+        let mut source = MockSource::new(vec![
+            event(EventType::KeyPress(Key::Tab)),
+            event(EventType::KeyRelease(Key::Tab)),
+        ]);
+        run_input(&mut source, Arc::clone(&state)).unwrap();
+        assert_eq!(
+            sink.wait_for(4, Duration::from_secs(2)),
+            [
+                EventType::KeyPress(Key::KeyA),
+                EventType::KeyRelease(Key::KeyA),
+                EventType::ButtonPress(Button::Left),
+                EventType::ButtonRelease(Button::Left),
+            ]
+        );
+        assert_eq!(
+            source.passed(),
+            [EventType::KeyPress(Key::Tab), EventType::KeyRelease(Key::Tab)]
+        );
+
+        // 序列触发时屏蔽原始的R
+        let sink_before = sink.events().len();
+        let mut source = MockSource::new(vec![
+            event(EventType::KeyPress(Key::KeyE)),
+            event(EventType::KeyRelease(Key::KeyE)),
+            event(EventType::KeyPress(Key::KeyR)),
+            event(EventType::KeyRelease(Key::KeyR)),
+        ]);
+        run_input(&mut source, state).unwrap();
+        assert_eq!(
+            sink.wait_for(sink_before + 2, Duration::from_secs(2))[sink_before..],
+            [EventType::KeyPress(Key::KeyQ), EventType::KeyRelease(Key::KeyQ)]
+        );
+        assert_eq!(
+            source.passed(),
+            [EventType::KeyPress(Key::KeyE), EventType::KeyRelease(Key::KeyE)]
+        );
+    }
+
+    // 使用手动时钟创建应用状态
+    fn manual_state(config: Config) -> (AppState, ManualClock) {
+        let clock = ManualClock::new();
+        let sink = MockSink::with_clock(Arc::new(clock.clone()));
+        (AppState::new(config, Arc::new(sink), Arc::new(clock.clone())), clock)
+    }
+
+    #[test]
+    fn test_yasuo_window_boundary() {
+        // E 和 R 间隔恰好 150ms 时触发并屏蔽 R，151ms 时不触发
+        for (gap, blocked) in [(149, true), (150, true), (151, false)] {
+            let (mut state, clock) = manual_state(Config::builtin());
+            state.set_champion("Yasuo".to_string());
+
+            assert!(state.handle_event(&clock_event(&clock, EventType::KeyPress(Key::KeyE)), None).is_some());
+            state.handle_event(&clock_event(&clock, EventType::KeyRelease(Key::KeyE)), None);
+            clock.advance_ms(gap);
+            let result = state.handle_event(&clock_event(&clock, EventType::KeyPress(Key::KeyR)), None);
+            assert_eq!(result.is_none(), blocked, "间隔 {}ms", gap);
+        }
+    }
+
+    #[test]
+    fn test_delayed_event_uses_event_time() {
+        // E 和 R 实际间隔 100ms，但 R 被延迟 100ms 才送达，仍然按事件时间判定为在窗口内
+        let (mut state, clock) = manual_state(Config::builtin());
+        state.set_champion("Yasuo".to_string());
+
+        state.handle_event(&clock_event(&clock, EventType::KeyPress(Key::KeyE)), None);
+        clock.advance_ms(100);
+        let pressed_r = clock_event(&clock, EventType::KeyPress(Key::KeyR));
+        clock.advance_ms(100);
+        // 先换算时间：触发的连招在单独的线程中执行，等待时会推进手动时钟
+        let (time, lag) = clock::to_monotonic(&clock, pressed_r.time);
+        assert_eq!(lag, Duration::from_millis(100));
+        assert_eq!(clock.now().duration_since(time), Duration::from_millis(100));
+        assert!(state.handle_event(&pressed_r, None).is_none());
+
+        // 事件时间晚于当前时间时不计算延迟
+        let clock = ManualClock::new();
+        let future = clock.system_now() + Duration::from_millis(50);
+        assert_eq!(clock::to_monotonic(&clock, future), (clock.now(), Duration::ZERO));
+    }
+
+    #[test]
+    fn test_device_conditions() {
+        let config = Config::parse(
+            r#"
+            general = { default_delay_ms = 0, devices = [{ name = "gaming" }] }
+            [[global_combos]]
+            name = "Tab"
+            sequence = [{ key = { Keyboard = "KeyA" } }]
+            trigger = { type = "SingleKey", key = "Tab" }
+            [[global_combos]]
+            name = "R"
+            sequence = [{ key = { Keyboard = "KeyQ" } }]
+            trigger = { type = "SingleKey", key = "KeyR" }
+            conditions = { devices = [{ vendor = 0x046d, product = 0xc08b }] }
+            "#,
+        )
+        .unwrap();
+        let device = |name: &str, vendor, product| DeviceInfo {
+            path: PathBuf::from("/dev/input/event3"),
+            name: name.to_string(),
+            vendor,
+            product,
+        };
+        let gaming = device("Gaming Keyboard", 0x1234, 0x0001);
+        let mouse = device("Logitech G502", 0x046d, 0xc08b);
+        let fired = |key, device: Option<DeviceInfo>| {
+            let sink = MockSink::new();
+            let state = Arc::new(RwLock::new(AppState::new(config.clone(), Arc::new(sink.clone()), Arc::new(SystemClock))));
+            let mut source = MockSource::new(vec![event(EventType::KeyPress(key))]);
+            if let Some(device) = device {
+                source = source.with_device(device);
+            }
+            run_input(&mut source, Arc::clone(&state)).unwrap();
+            let fired = state.read().unwrap().engine.resolutions[&key].fired.clone();
+            let history_device = state.read().unwrap().engine.history.current_device().map(|device| device.name.clone());
+            (fired, history_device)
+        };
+
+        // 全局限制按名称匹配，设备信息记录在按键历史中
+        assert_eq!(fired(Key::Tab, Some(gaming.clone())), (vec![0], Some(gaming.name.clone())));
+        assert_eq!(fired(Key::Tab, Some(mouse.clone())).0, [] as [usize; 0]);
+        // 后端无法区分设备时不触发有设备限制的连招
+        assert_eq!(fired(Key::Tab, None), (vec![], None));
+        // 连招自己的设备限制取代全局限制
+        assert_eq!(fired(Key::KeyR, Some(mouse)).0, [1]);
+        assert_eq!(fired(Key::KeyR, Some(gaming)).0, [] as [usize; 0]);
+    }
+
+    #[test]
+    fn test_listen_mode_refuses_blocking_combos() {
+        let names = |state: &AppState| state.engine.combos.iter().map(|combo| combo.name.clone()).collect::<Vec<_>>();
+        let mut state = AppState::new(Config::builtin(), Arc::new(MockSink::new()), Arc::new(SystemClock));
+        state.set_champion("Yasuo".to_string());
+        assert_eq!(state.engine.combos.len(), 3);
+
+        // 无法屏蔽时不加载需要屏蔽原始按键的亚索连招
+        state.set_can_block(false);
+        assert_eq!(names(&state), ["Tab触发A+左键"]);
+
+        state.allow_unblocked = true;
+        state.load_combos();
+        assert_eq!(state.engine.combos.len(), 3);
+    }
+
+    #[test]
+    fn test_dry_run_does_not_block() {
+        let sink = MockSink::new();
+        let mut state = AppState::new(Config::builtin(), Arc::new(sink.clone()), Arc::new(SystemClock));
+        state.engine.dry_run = true;
+        state.set_champion("Yasuo".to_string());
+        let state = Arc::new(RwLock::new(state));
+
+        // 照常判定触发，但原始的 R 不被屏蔽
+        let mut source = MockSource::new(vec![
+            event(EventType::KeyPress(Key::KeyE)),
+            event(EventType::KeyPress(Key::KeyR)),
+        ]);
+        run_input(&mut source, Arc::clone(&state)).unwrap();
+        assert_eq!(state.read().unwrap().engine.resolutions[&Key::KeyR].fired.len(), 1);
+        assert_eq!(
+            source.passed(),
+            [EventType::KeyPress(Key::KeyE), EventType::KeyPress(Key::KeyR)]
+        );
+        assert!(!state.write().unwrap().engine.is_key_blocked(Key::KeyR));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parse = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["hero-rs"].iter().chain(args)).map_err(|e| e.to_string())?;
+            cli.validate().map(|_| cli)
+        };
+        Cli::command().debug_assert();
+
+        let cli = parse(&[]).unwrap();
+        assert_eq!(cli.command_or_run(), Command::Run(RunArgs::default()));
+        assert_eq!((cli.backend, cli.log_level, cli.dry_run, cli.no_repl), (Backend::Rdev, Level::Info, false, false));
+        let run_args = RunArgs { mode: CaptureMode::Listen, allow_unblocked: true, ..Default::default() };
+        assert_eq!(parse(&["--mode", "listen", "--allow-unblocked"]).unwrap().command_or_run(), Command::Run(run_args.clone()));
+        assert_eq!(parse(&["run", "--mode", "listen", "--allow-unblocked"]).unwrap().command_or_run(), Command::Run(run_args));
+        assert!(parse(&["--mode", "exclusive"]).is_err());
+        let cli = parse(&["--backend", "evdev", "--device", "/dev/input/event3"]).unwrap();
+        assert_eq!(cli.backend, Backend::Evdev);
+        assert_eq!(cli.devices, [PathBuf::from("/dev/input/event3")]);
+
+        assert!(parse(&["--backend", "x11"]).is_err());
+        assert!(parse(&["--device", "/dev/input/event3"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+        assert_eq!(parse(&["doctor"]).unwrap().command_or_run(), Command::Doctor);
+
+        // 全局参数可以放在子命令前后
+        let cli = parse(&["--config", "my.toml", "simulate", "亚索E+R触发QR", "--dry-run", "--profile", "Yasuo", "--log-level", "debug"]).unwrap();
+        assert_eq!(cli.command_or_run(), Command::Simulate { combo: "亚索E+R触发QR".to_string() });
+        assert_eq!(cli.config, Some(PathBuf::from("my.toml")));
+        assert_eq!(cli.profile.as_deref(), Some("Yasuo"));
+        assert_eq!(cli.log_level, Level::Debug);
+        assert!(cli.dry_run);
+        assert!(parse(&["replay", "--no-repl", "e.jsonl"]).unwrap().no_repl);
+        assert!(parse(&["record"]).is_err());
+        assert!(parse(&["check", "--mode", "grab"]).is_err());
+    }
+
+    #[test]
+    fn test_combo_commands() {
+        let mut state = AppState::new(Config::builtin(), Arc::new(MockSink::new()), Arc::new(SystemClock));
+        state.set_champion("Yasuo".to_string());
+        
+        // 中文名称按字模糊匹配，不唯一时不选择
+        let idx = state.lookup_combo("亚索r").unwrap();
+        assert_eq!(state.engine.combos[idx].name, "亚索E+R触发QR");
+        assert_eq!(state.lookup_combo("亚索"), None);
+        assert_eq!(state.lookup_combo("不存在"), None);
+        
+        // 禁用后不再触发，切换英雄后仍然保持禁用
+        state.set_combo_active(idx, false);
+        state.clear_champion();
+        state.set_champion("亚索".to_string());
+        let idx = state.lookup_combo("亚索E+R触发QR").unwrap();
+        assert!(!state.engine.combos[idx].active);
+        let mut resolution = Resolution::default();
+        state.engine.index.dispatch(Key::KeyE, Instant::now());
+        state.engine.index.dispatch(Key::KeyR, Instant::now());
+        state.engine.resolve(Key::KeyR, &mut resolution);
+        assert!(resolution.fired.is_empty());
+        assert!(matches!(resolution.reports[0].verdict, Verdict::Inactive));
+        
+        assert_eq!(state.completions("sh"), ["show"]);
+        assert_eq!(state.completions("pro"), ["profiles"]);
+        assert_eq!(state.completions("enable 亚索"), ["enable 亚索E+D触发QD", "enable 亚索E+R触发QR"]);
+        assert_eq!(state.completions("champion ya"), ["champion Yasuo"]);
+        
+        // 行编辑器的 Tab 补全使用光标之前的内容，替换整行
+        let helper = CommandHelper { state: Arc::new(RwLock::new(state)) };
+        let history = rustyline::history::DefaultHistory::new();
+        let ctx = rustyline::Context::new(&history);
+        assert_eq!(helper.complete("champion ya", 11, &ctx).unwrap(), (0, vec!["champion Yasuo".to_string()]));
+        assert_eq!(helper.complete("sh 亚索", 2, &ctx).unwrap(), (0, vec!["show".to_string()]));
+    }
+
+    #[test]
+    fn test_run_command() {
+        assert_eq!(parse_run_args(" 亚索E+R触发QR").unwrap(), ("亚索E+R触发QR".to_string(), Duration::ZERO));
+        assert_eq!(parse_run_args("--delay 3000 my combo").unwrap(), ("my combo".to_string(), Duration::from_secs(3)));
+        assert!(parse_run_args("--delay").is_err());
+        assert!(parse_run_args("combo --delay soon").is_err());
+        assert!(parse_run_args("--delay 100").is_err());
+
+        // Manual 触发的连招不会被按键触发，只能手动执行
+        let mut config = Config::builtin();
+        config.global_combos.push(ComboConfig {
+            name: "手动回城".to_string(),
+            sequence: vec![StepConfig { key: ActionKey::Keyboard(Key::KeyB), delay_before_ms: 0, delay_after_ms: 0 }],
+            trigger: TriggerType::Manual,
+            block_original_input: false,
+            active: true,
+            conditions: Conditions::default(),
+            priority: 0,
+        });
+        let clock = ManualClock::new();
+        let sink = MockSink::with_clock(Arc::new(clock.clone()));
+        let state = AppState::new(config, Arc::new(sink.clone()), Arc::new(clock.clone()));
+        let idx = state.lookup_combo("手动").unwrap();
+        state.run_combo(idx, Duration::from_millis(2500));
+        let events = sink.wait_for(2, Duration::from_secs(1));
+        assert_eq!(events, [EventType::KeyPress(Key::KeyB), EventType::KeyRelease(Key::KeyB)]);
+        assert!(clock.elapsed() >= Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn test_published_events() {
+        let mut state = AppState::new(Config::builtin(), Arc::new(MockSink::new()), Arc::new(SystemClock));
+        let events = state.engine.events.subscribe();
+        
+        // 暂停状态没有变化时不发布
+        state.set_paused(true);
+        state.set_paused(true);
+        state.set_champion("Yasuo".to_string());
+        state.engine.block_key(Key::KeyE);
+        let idx = state.lookup_combo("亚索r").unwrap();
+        state.run_combo(idx, Duration::ZERO);
+        
+        let combo = "亚索E+R触发QR".to_string();
+        let received: Vec<AppEvent> = (0..8).map(|_| events.recv_timeout(Duration::from_secs(1)).unwrap().event).collect();
+        assert_eq!(received, [
+            AppEvent::PauseToggled { paused: true },
+            AppEvent::ChampionSwitched { champion: Some("Yasuo".to_string()) },
+            AppEvent::KeyBlocked { key: "E".to_string() },
+            AppEvent::ComboTriggered { combo: combo.clone(), key: None },
+            AppEvent::ComboStarted { combo: combo.clone() },
+            AppEvent::ComboStepExecuted { combo: combo.clone(), step: 1, key: "Q".to_string() },
+            AppEvent::ComboStepExecuted { combo: combo.clone(), step: 2, key: "R".to_string() },
+            AppEvent::ComboCompleted { combo },
+        ]);
+        
+        // 输入事件和处理结果，统计由监听者完成
+        state.set_paused(false);
+        while events.try_recv().is_ok() {}
+        state.handle_event(&event(EventType::KeyPress(Key::KeyE)), None);
+        state.handle_event(&event(EventType::MouseMove { x: 1.0, y: 1.0 }), None);
+        let received = events.recv_timeout(Duration::from_secs(1)).unwrap().event;
+        assert_eq!(received, AppEvent::InputObserved { input: EventType::KeyPress(Key::KeyE), device: None, blocked: true });
+        assert!(events.try_recv().is_err());
+        #[cfg(any(unix, feature = "http"))]
+        {
+            let stats = state.stats();
+            assert_eq!((stats["events"].as_u64(), stats["keys_blocked"].as_u64()), (Some(1), Some(1)));
+        }
+    }
+
+    #[test]
+    fn test_combo_wizard() {
+        let state = RwLock::new(AppState::new(Config::builtin(), Arc::new(MockSink::new()), Arc::new(SystemClock)));
+        
+        // 新建全局连招：名称、触发条件（第一次输入有误会重新询问）、两个步骤、屏蔽、默认优先级
+        let mut input = "测试连招\nseq E,R\nseq E,R 150ms\nQ 0 50\nmouse:left\n\ny\n\n".as_bytes();
+        combo_wizard(&state, &mut input, None).unwrap();
+        {
+            let state = state.read().unwrap();
+            let combo = &state.engine.combos[state.lookup_combo("测试连招").unwrap()];
+            assert_eq!(combo.trigger.to_text(), "seq E,R 150ms");
+            assert_eq!(combo.sequence, [ActionKey::Keyboard(Key::KeyQ), ActionKey::Mouse(Button::Left)]);
+            assert_eq!(combo.delays, [(0, 50), (0, 0)]);
+            assert!(combo.block_original_input);
+            assert_eq!(state.engine.config.global_combos.len(), 2);
+        }
+        
+        // 编辑：直接回车保留名称、触发条件和步骤，只修改屏蔽和优先级
+        let mut input = "\n\n\nn\n5\n".as_bytes();
+        combo_wizard(&state, &mut input, Some("测试")).unwrap();
+        {
+            let state = state.read().unwrap();
+            let combo = &state.engine.combos[state.lookup_combo("测试连招").unwrap()];
+            assert_eq!(combo.trigger.to_text(), "seq E,R 150ms");
+            assert_eq!(combo.delays, [(0, 50), (0, 0)]);
+            assert!(!combo.block_original_input);
+            assert_eq!(combo.priority, 5);
+            assert_eq!(state.engine.config.global_combos.len(), 2);
+        }
+        
+        // 与加载配置文件相同的检查：同一组中名称不能重复
+        let mut input = "Tab触发A+左键\nkey F\nF\n\nn\n0\n".as_bytes();
+        assert!(combo_wizard(&state, &mut input, None).unwrap_err().contains("重复"));
+        // 输入提前结束时取消
+        let mut input = "半途而废\n".as_bytes();
+        assert!(combo_wizard(&state, &mut input, None).is_err());
+        assert_eq!(state.read().unwrap().engine.config.global_combos.len(), 2);
+    }
+
+    #[test]
+    fn test_source_script() {
+        let state = Arc::new(RwLock::new(AppState::new(Config::builtin(), Arc::new(MockSink::new()), Arc::new(SystemClock))));
+        let path = std::env::temp_dir().join(format!("hero-rs-source-{}.txt", std::process::id()));
+        
+        // 脚本中的向导从后续行读取回答；脚本引用自身时在嵌套上限处停止
+        let script = format!(
+            "# 启动脚本\nchampion Yasuo\n\ndisable 亚索E+D\nbind F9 toggle\nnew combo\nn\n脚本连招\nkey F\nQ\n\nn\n0\nsource {}\n",
+            path.display()
+        );
+        std::fs::write(&path, script).unwrap();
+        handle_command(&format!("source {}", path.display()), Arc::clone(&state), &mut std::io::empty());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(SOURCE_DEPTH.get(), 0);
+        
+        let mut state = state.write().unwrap();
+        assert_eq!(state.engine.current_champion.as_deref(), Some("Yasuo"));
+        assert!(!state.engine.combos[state.lookup_combo("亚索E+D触发QD").unwrap()].active);
+        assert_eq!(state.engine.config.global_combos.iter().filter(|combo| combo.name == "脚本连招").count(), 1);
+        assert!(source_file(Path::new("不存在的脚本"), &Arc::new(RwLock::new(AppState::new(Config::builtin(), Arc::new(MockSink::new()), Arc::new(SystemClock))))).is_err());
+        
+        // 绑定的按键把命令交给命令线程，暂停时也有效
+        let (tx, rx) = mpsc::channel();
+        state.engine.command_tx = Some(tx);
+        state.set_paused(true);
+        assert!(state.handle_event(&clock_event(&SystemClock, EventType::KeyPress(Key::F9)), None).is_some());
+        assert_eq!(rx.try_recv().unwrap(), "toggle");
+        state.unbind_key(Key::F9);
+        state.handle_event(&clock_event(&SystemClock, EventType::KeyPress(Key::F9)), None);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_handle_rpc() {
+        let state = RwLock::new(AppState::new(Config::builtin(), Arc::new(MockSink::new()), Arc::new(SystemClock)));
+        
+        let result = handle_rpc(&state, "set_champion", &json!({ "name": "Yasuo" })).unwrap();
+        assert_eq!(result, json!({ "champion": "Yasuo" }));
+        let result = handle_rpc(&state, "disable", &json!({ "combo": "亚索r" })).unwrap();
+        assert_eq!(result, json!({ "combo": "亚索E+R触发QR", "active": false }));
+        assert_eq!(handle_rpc(&state, "pause", &Value::Null).unwrap(), json!({ "paused": true }));
+        
+        let status = handle_rpc(&state, "status", &Value::Null).unwrap();
+        assert_eq!(status["champion"], "Yasuo");
+        assert_eq!(status["paused"], true);
+        assert_eq!(status["active_combos"], status["combos"].as_u64().unwrap() - 1);
+        let list = handle_rpc(&state, "list_combos", &Value::Null).unwrap();
+        assert!(list.as_array().unwrap().contains(&json!({ "name": "亚索E+R触发QR", "active": false, "trigger": "seq E,R 150ms" })));
+        
+        // 错误：连招不唯一、没有这个英雄、缺少参数、未知方法、没有配置文件可以重新加载
+        assert_eq!(handle_rpc(&state, "enable", &json!({ "combo": "亚索" })).unwrap_err().code, ipc::COMMAND_FAILED);
+        assert_eq!(handle_rpc(&state, "set_champion", &json!({ "name": "Yasou" })).unwrap_err().code, ipc::COMMAND_FAILED);
+        assert_eq!(state.read().unwrap().engine.current_champion.as_deref(), Some("Yasuo"));
+        assert_eq!(handle_rpc(&state, "run_combo", &json!({})).unwrap_err().code, ipc::INVALID_PARAMS);
+        assert_eq!(handle_rpc(&state, "explode", &Value::Null).unwrap_err().code, ipc::METHOD_NOT_FOUND);
+        assert_eq!(handle_rpc(&state, "reload", &Value::Null).unwrap_err().code, ipc::COMMAND_FAILED);
+        
+        let cli = Cli::try_parse_from(["hero-rs", "ctl", "run", "my", "combo", "--delay", "500"]).unwrap();
+        let Some(Command::Ctl { command }) = cli.command else { panic!("应为 ctl 子命令") };
+        assert_eq!(command.request(), ("run_combo", json!({ "combo": "my combo", "delay_ms": 500 })));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_handle_http() {
+        let state = RwLock::new(AppState::new(Config::builtin(), Arc::new(MockSink::new()), Arc::new(SystemClock)));
+        let send = |method: &str, path: &str, body: Value| {
+            let path = path.split('/').filter(|segment| !segment.is_empty()).map(String::from).collect();
+            handle_http(&state, &http::Request { method: method.to_string(), path, query: Vec::new(), body })
+        };
+        let json = |response: http::Response| match response.body {
+            http::Body::Json(value) => (response.status, value),
+            body => panic!("应为 JSON 响应: {:?}", body),
+        };
+        
+        assert!(matches!(send("GET", "/", Value::Null).body, http::Body::Html(_)));
+        assert_eq!(json(send("PUT", "api/profile", json!({ "name": "Yasuo" }))).1, json!({ "champion": "Yasuo" }));
+        assert_eq!(json(send("PUT", "api/profile", json!({ "name": "Zed" }))).0, 404);
+        let (_, profiles) = json(send("GET", "api/profiles", Value::Null));
+        assert_eq!(profiles[0]["current"], true);
+        
+        // 新建：与配置文件相同的检查，名称重复或步骤无效时拒绝
+        let form = json!({ "name": "网页连招", "trigger": "key F", "steps": ["Q 0 50", "mouse:left"], "priority": 2 });
+        let (status, created) = json(send("POST", "api/combos", form.clone()));
+        assert_eq!(status, 201);
+        assert_eq!(created["combo"]["steps"], json!(["Q 0 50", "mouse:left 0 0"]));
+        assert_eq!(json(send("POST", "api/combos", form)).0, 422);
+        assert_eq!(json(send("POST", "api/combos", json!({ "name": "x", "trigger": "key F", "steps": ["??"] }))).0, 400);
+        assert_eq!(json(send("POST", "api/combos", json!({ "name": "x", "steps": ["Q"] }))).0, 400);
+        
+        // 修改：没有给出的字段保持不变；保存需要配置文件
+        let (status, edited) = json(send("PUT", "api/combos/网页连招", json!({ "block_original_input": true })));
+        assert_eq!(status, 200);
+        assert_eq!(edited["combo"]["trigger"], "key F");
+        assert_eq!(edited["combo"]["block_original_input"], true);
+        assert_eq!(json(send("PUT", "api/combos/网页连招", json!({ "save": true }))).0, 409);
+        assert_eq!(json(send("GET", "api/combos/亚索r", Value::Null)).1["champion"], "Yasuo");
+        
+        assert_eq!(json(send("POST", "api/combos/网页连招/disable", Value::Null)).1["active"], false);
+        send("POST", "api/combos/网页连招/run", Value::Null);
+        let (_, stats) = json(send("GET", "api/stats", Value::Null));
+        assert_eq!(stats["executions"]["网页连招"], 1);
+        assert_eq!(json(send("GET", "api/nope", Value::Null)).0, 404);
+        assert!(matches!(send("GET", "api/events", Value::Null).body, http::Body::Events { input: false, .. }));
+    }
+
+    #[test]
+    fn test_startup_profile() {
+        let mut config = Config::builtin();
+        assert_eq!(startup_profile(None, None, &config), None);
+        config.current_champion = Some("Yasuo".to_string());
+        assert_eq!(startup_profile(None, None, &config).as_deref(), Some("Yasuo"));
+
+        config.champion_specific.insert("Ahri".to_string(), ChampionConfig { name: "阿狸".to_string(), combos: Vec::new() });
+        assert_eq!(startup_profile(None, Some("阿狸".to_string()), &config).as_deref(), Some("阿狸"));
+        assert_eq!(startup_profile(None, Some("Zed".to_string()), &config).as_deref(), Some("Yasuo"));
+        assert_eq!(startup_profile(Some("Ahri"), Some("Yasuo".to_string()), &config).as_deref(), Some("Ahri"));
+    }
+
+    #[test]
+    fn test_find_combo() {
+        let config = Config::builtin();
+        assert_eq!(find_combo(&config, "tab触发a+左键").unwrap().name, "Tab触发A+左键");
+        assert_eq!(find_combo(&config, "亚索E+D触发QD").unwrap().name, "亚索E+D触发QD");
+        assert!(find_combo(&config, "不存在").is_none());
+
+        let combo = Combo::from_config(find_combo(&config, "亚索E+R触发QR").unwrap(), 50);
+        assert_eq!(combo.duration(), Duration::from_millis(50 + 50 + 2 * 50));
     }
 
     #[test]
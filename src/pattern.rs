@@ -0,0 +1,642 @@
+use rdev::Key;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+// 触发模式语法：
+//   E W R          依次按下 E、W、R
+//   (R|D)          R 或 D
+//   [Q W E]        Q、W、E 中的任意一个
+//   .              任意按键
+//   X? X* X+       可选、零次或多次、一次或多次
+//   X <150ms       X 与前一个按键的间隔不超过 150 毫秒
+// 单个字母或数字表示对应按键，其他按键使用 rdev 名称（例如 Space、ShiftLeft）。
+// 例如：E [W]? (R|D) <150ms
+
+// 一个状态可以匹配的按键集合
+#[derive(Debug, Clone, PartialEq)]
+enum KeySet {
+    Any,
+    Keys(Vec<Key>),
+}
+
+impl KeySet {
+    fn contains(&self, key: Key) -> bool {
+        match self {
+            KeySet::Any => true,
+            KeySet::Keys(keys) => keys.contains(&key),
+        }
+    }
+}
+
+// 语法树节点
+#[derive(Debug, Clone)]
+enum Node {
+    Keys(KeySet),
+    Seq(Vec<Node>),
+    Alt(Vec<Node>),
+    Optional(Box<Node>),
+    Star(Box<Node>),
+    Plus(Box<Node>),
+    Gap(Box<Node>, u64),
+}
+
+fn min_keys(node: &Node) -> usize {
+    match node {
+        Node::Keys(_) => 1,
+        Node::Seq(nodes) => nodes.iter().map(min_keys).sum(),
+        Node::Alt(branches) => branches.iter().map(min_keys).min().unwrap_or(0),
+        Node::Optional(_) | Node::Star(_) => 0,
+        Node::Plus(inner) | Node::Gap(inner, _) => min_keys(inner),
+    }
+}
+
+// 自动机状态
+#[derive(Debug, Clone)]
+enum State {
+    Key {
+        keys: KeySet,
+        gap_ms: Option<u64>,
+        next: usize,
+    },
+    Split(usize, usize),
+    Match,
+}
+
+// 编译后的按键自动机（NFA）
+#[derive(Debug)]
+pub struct Automaton {
+    states: Vec<State>,
+    start: usize,
+    // 从第一个按键到最后一个按键的总时间限制
+    window_ms: Option<u64>,
+    // 允许夹杂的无关按键数量
+    max_skips: usize,
+    // 匹配时忽略的按键
+    ignore_keys: Vec<Key>,
+}
+
+impl Automaton {
+    // 从简单按键序列构建自动机
+    pub fn sequence(
+        keys: &[Key],
+        timeout_ms: u64,
+        max_interleaved: usize,
+        ignore_keys: &[Key],
+    ) -> Self {
+        let node = Node::Seq(
+            keys.iter()
+                .map(|&key| Node::Keys(KeySet::Keys(vec![key])))
+                .collect(),
+        );
+        let mut automaton = Self::compile(&node);
+        automaton.window_ms = Some(timeout_ms);
+        automaton.max_skips = max_interleaved;
+        automaton.ignore_keys = ignore_keys.to_vec();
+        automaton
+    }
+
+    fn compile(node: &Node) -> Self {
+        let mut states = vec![State::Match];
+        let start = compile_node(&mut states, node, 0, None);
+        Automaton {
+            states,
+            start,
+            window_ms: None,
+            max_skips: 0,
+            ignore_keys: Vec::new(),
+        }
+    }
+
+    // 从 pc 出发沿空转移展开，收集所有可消费按键的状态；返回是否到达匹配状态
+    fn closure(
+        &self,
+        pc: usize,
+        thread: Thread,
+        out: &mut Vec<Thread>,
+        visited: &mut [bool],
+    ) -> bool {
+        visited.fill(false);
+        self.closure_inner(pc, thread, out, visited)
+    }
+
+    fn closure_inner(
+        &self,
+        pc: usize,
+        thread: Thread,
+        out: &mut Vec<Thread>,
+        visited: &mut [bool],
+    ) -> bool {
+        // 可能为空的循环（例如 (E?)*）会回到已访问的状态
+        if visited[pc] {
+            return false;
+        }
+        visited[pc] = true;
+
+        match &self.states[pc] {
+            State::Match => true,
+            State::Key { .. } => {
+                push_thread(out, Thread { pc, ..thread });
+                false
+            }
+            State::Split(a, b) => {
+                let matched_a = self.closure_inner(*a, thread, out, visited);
+                let matched_b = self.closure_inner(*b, thread, out, visited);
+                matched_a || matched_b
+            }
+        }
+    }
+}
+
+// 按节点从后往前编译，next 为匹配完成后的后续状态
+fn compile_node(states: &mut Vec<State>, node: &Node, next: usize, gap_ms: Option<u64>) -> usize {
+    match node {
+        Node::Keys(keys) => {
+            states.push(State::Key {
+                keys: keys.clone(),
+                gap_ms,
+                next,
+            });
+            states.len() - 1
+        }
+        Node::Seq(nodes) => nodes
+            .iter()
+            .rev()
+            .fold(next, |next, node| compile_node(states, node, next, gap_ms)),
+        Node::Alt(branches) => {
+            let mut starts = branches
+                .iter()
+                .map(|branch| compile_node(states, branch, next, gap_ms))
+                .collect::<Vec<_>>();
+            let mut start = starts.pop().unwrap();
+            while let Some(other) = starts.pop() {
+                states.push(State::Split(other, start));
+                start = states.len() - 1;
+            }
+            start
+        }
+        Node::Optional(inner) => {
+            let body = compile_node(states, inner, next, gap_ms);
+            states.push(State::Split(body, next));
+            states.len() - 1
+        }
+        Node::Star(inner) => {
+            states.push(State::Match); // 占位，稍后替换为分支
+            let split = states.len() - 1;
+            let body = compile_node(states, inner, split, gap_ms);
+            states[split] = State::Split(body, next);
+            split
+        }
+        Node::Plus(inner) => {
+            states.push(State::Match); // 占位，稍后替换为分支
+            let split = states.len() - 1;
+            let body = compile_node(states, inner, split, gap_ms);
+            states[split] = State::Split(body, next);
+            body
+        }
+        Node::Gap(inner, ms) => compile_node(states, inner, next, Some(*ms)),
+    }
+}
+
+// 正在进行中的一次匹配
+#[derive(Debug, Clone, Copy)]
+struct Thread {
+    pc: usize,
+    start: Instant,
+    last: Option<Instant>,
+    skipped: usize,
+}
+
+// 同一状态只保留一个线程：优先保留开始时间更晚、跳过按键更少的
+fn push_thread(threads: &mut Vec<Thread>, thread: Thread) {
+    match threads.iter_mut().find(|t| t.pc == thread.pc) {
+        Some(existing) => {
+            if (thread.start, std::cmp::Reverse(thread.skipped))
+                > (existing.start, std::cmp::Reverse(existing.skipped))
+            {
+                *existing = thread;
+            }
+        }
+        None => threads.push(thread),
+    }
+}
+
+// 自动机的运行状态，每次按键时推进
+// 缓冲区在多次按键之间复用，推进时不分配内存
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    automaton: Arc<Automaton>,
+    threads: Vec<Thread>,
+    next: Vec<Thread>,
+    visited: Vec<bool>,
+}
+
+impl Matcher {
+    pub fn new(automaton: Arc<Automaton>) -> Self {
+        let states = automaton.states.len();
+        Matcher {
+            automaton,
+            threads: Vec::with_capacity(states),
+            next: Vec::with_capacity(states),
+            visited: vec![false; states],
+        }
+    }
+
+    // 输入一个按下的按键，返回是否刚好完成匹配
+    pub fn advance(&mut self, key: Key, time: Instant) -> bool {
+        let Matcher {
+            automaton,
+            threads,
+            next,
+            visited,
+        } = self;
+
+        // 每个按键都可能是一次新匹配的开头
+        let seed = Thread {
+            pc: automaton.start,
+            start: time,
+            last: None,
+            skipped: 0,
+        };
+        automaton.closure(automaton.start, seed, threads, visited);
+
+        next.clear();
+        let mut matched = false;
+        for &thread in threads.iter() {
+            // 超过总时间限制的匹配直接丢弃
+            if let Some(window) = automaton.window_ms {
+                if time.duration_since(thread.start).as_millis() as u64 > window {
+                    continue;
+                }
+            }
+
+            let State::Key {
+                keys,
+                gap_ms,
+                next: target,
+            } = &automaton.states[thread.pc]
+            else {
+                continue;
+            };
+
+            let gap_ok = match (gap_ms, thread.last) {
+                (Some(gap), Some(last)) => time.duration_since(last).as_millis() as u64 <= *gap,
+                _ => true,
+            };
+
+            if keys.contains(key) && gap_ok {
+                let advanced = Thread {
+                    last: Some(time),
+                    ..thread
+                };
+                matched |= automaton.closure(*target, advanced, next, visited);
+            } else if thread.last.is_some() {
+                // 已经开始的匹配可以跳过忽略的按键或有限数量的无关按键
+                if automaton.ignore_keys.contains(&key) {
+                    push_thread(next, thread);
+                } else if thread.skipped < automaton.max_skips {
+                    push_thread(
+                        next,
+                        Thread {
+                            skipped: thread.skipped + 1,
+                            ..thread
+                        },
+                    );
+                }
+            }
+        }
+
+        // 匹配成功后重新开始，避免同一组按键重复触发
+        if matched {
+            next.clear();
+        }
+        std::mem::swap(threads, next);
+        matched
+    }
+}
+
+// 解析后的触发模式
+#[derive(Clone)]
+pub struct Pattern {
+    source: String,
+    automaton: Arc<Automaton>,
+    min_keys: usize,
+}
+
+impl Pattern {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let node = parser.parse_alt()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("模式 \"{}\" 中有多余的内容: {:?}", source, token));
+        }
+
+        Ok(Pattern {
+            source: source.to_string(),
+            automaton: Arc::new(Automaton::compile(&node)),
+            min_keys: min_keys(&node),
+        })
+    }
+
+    // 模式的原始文本
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn automaton(&self) -> Arc<Automaton> {
+        Arc::clone(&self.automaton)
+    }
+
+    // 匹配该模式至少需要的按键数
+    pub fn min_keys(&self) -> usize {
+        self.min_keys
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.source)
+    }
+}
+
+impl serde::Serialize for Pattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+// 词法单元
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Gap(u64),
+    Symbol(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else if "()[]|?*+.".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else if c == '<' {
+            chars.next();
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || "()[]|?*+.,<".contains(c) {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+            let ms = text
+                .strip_suffix("ms")
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| format!("无效的时间限制: <{}，应为 <150ms 的形式", text))?;
+            tokens.push(Token::Gap(ms));
+        } else if c.is_alphanumeric() || c == '_' {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else {
+            return Err(format!("模式 \"{}\" 中有无法识别的字符: {}", source, c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// 把按键名称转换为 rdev 按键
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphabetic() {
+            return parse_key(&format!("Key{}", c.to_ascii_uppercase()));
+        }
+        if c.is_ascii_digit() {
+            return parse_key(&format!("Num{}", c));
+        }
+    }
+
+    serde_json::from_value(serde_json::Value::String(name.to_string()))
+        .map_err(|_| format!("未知按键: {}", name))
+}
+
+// 按键的简写名称，与 parse_key 互逆：字母和数字键使用单个字符，其他按键使用 rdev 名称
+pub fn key_name(key: Key) -> String {
+    let name = format!("{:?}", key);
+    match name
+        .strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Num"))
+    {
+        Some(short) if short.len() == 1 => short.to_string(),
+        _ => name,
+    }
+}
+
+// 递归下降解析器
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(c)) if *c == symbol => {
+                self.pos += 1;
+                Ok(())
+            }
+            other => Err(format!("应为 '{}'，实际为 {:?}", symbol, other)),
+        }
+    }
+
+    // alt := seq ('|' seq)*
+    fn parse_alt(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.parse_seq()?];
+        while self.peek() == Some(&Token::Symbol('|')) {
+            self.pos += 1;
+            branches.push(self.parse_seq()?);
+        }
+
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    // seq := item+
+    fn parse_seq(&mut self) -> Result<Node, String> {
+        let mut items = Vec::new();
+        while let Some(token) = self.peek() {
+            if matches!(token, Token::Symbol('|') | Token::Symbol(')')) {
+                break;
+            }
+            items.push(self.parse_item()?);
+        }
+
+        match items.len() {
+            0 => Err("模式中有空的序列".to_string()),
+            1 => Ok(items.pop().unwrap()),
+            _ => Ok(Node::Seq(items)),
+        }
+    }
+
+    // item := primary ('?' | '*' | '+')? ('<' N 'ms')?
+    fn parse_item(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_primary()?;
+
+        match self.peek() {
+            Some(Token::Symbol('?')) => node = Node::Optional(Box::new(node)),
+            Some(Token::Symbol('*')) => node = Node::Star(Box::new(node)),
+            Some(Token::Symbol('+')) => node = Node::Plus(Box::new(node)),
+            _ => return Ok(self.parse_gap(node)),
+        }
+        self.pos += 1;
+
+        Ok(self.parse_gap(node))
+    }
+
+    fn parse_gap(&mut self, node: Node) -> Node {
+        if let Some(Token::Gap(ms)) = self.peek() {
+            let ms = *ms;
+            self.pos += 1;
+            return Node::Gap(Box::new(node), ms);
+        }
+        node
+    }
+
+    // primary := KEY | '.' | '(' alt ')' | '[' KEY+ ']'
+    fn parse_primary(&mut self) -> Result<Node, String> {
+        let token = self.peek().cloned().ok_or("模式意外结束")?;
+        self.pos += 1;
+
+        match token {
+            Token::Name(name) => Ok(Node::Keys(KeySet::Keys(vec![parse_key(&name)?]))),
+            Token::Symbol('.') => Ok(Node::Keys(KeySet::Any)),
+            Token::Symbol('(') => {
+                let node = self.parse_alt()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Token::Symbol('[') => {
+                let mut keys = Vec::new();
+                while let Some(Token::Name(name)) = self.peek() {
+                    keys.push(parse_key(name)?);
+                    self.pos += 1;
+                }
+                self.expect(']')?;
+                if keys.is_empty() {
+                    return Err("按键集合 [] 不能为空".to_string());
+                }
+                Ok(Node::Keys(KeySet::Keys(keys)))
+            }
+            other => Err(format!("意外的符号: {:?}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // 依次输入按键（按键, 毫秒偏移），返回每次输入后是否匹配
+    fn run(pattern: &str, presses: &[(Key, u64)]) -> Vec<bool> {
+        let mut matcher = Matcher::new(Pattern::parse(pattern).unwrap().automaton());
+        let now = Instant::now();
+        presses
+            .iter()
+            .map(|&(key, offset)| matcher.advance(key, now + Duration::from_millis(offset)))
+            .collect()
+    }
+
+    #[test]
+    fn test_optional_and_alternation() {
+        let pattern = "E [W]? (R|D) <150ms";
+        assert_eq!(
+            run(pattern, &[(Key::KeyE, 0), (Key::KeyR, 100)]),
+            [false, true]
+        );
+        assert_eq!(
+            run(
+                pattern,
+                &[(Key::KeyE, 0), (Key::KeyW, 100), (Key::KeyD, 200)]
+            ),
+            [false, false, true]
+        );
+        // 间隔超过 150ms
+        assert_eq!(
+            run(pattern, &[(Key::KeyE, 0), (Key::KeyR, 200)]),
+            [false, false]
+        );
+        // 中间夹杂其他按键
+        assert_eq!(
+            run(
+                pattern,
+                &[(Key::KeyE, 0), (Key::KeyQ, 50), (Key::KeyR, 100)]
+            ),
+            [false, false, false]
+        );
+    }
+
+    #[test]
+    fn test_repeat_and_wildcard() {
+        // 匹配成功后重新开始，第三个按键不会再次触发
+        assert_eq!(
+            run("Q+ .", &[(Key::KeyQ, 0), (Key::KeyQ, 10), (Key::Space, 20)]),
+            [false, true, false]
+        );
+        assert_eq!(
+            run(
+                "Q+ Space",
+                &[(Key::KeyQ, 0), (Key::KeyQ, 10), (Key::Space, 20)]
+            ),
+            [false, false, true]
+        );
+        assert_eq!(run("E* R", &[(Key::KeyR, 0)]), [true]);
+    }
+
+    #[test]
+    fn test_min_keys() {
+        assert_eq!(Pattern::parse("E [W]? (R|D) <150ms").unwrap().min_keys(), 2);
+        assert_eq!(Pattern::parse("Q+ (E R | D)").unwrap().min_keys(), 2);
+        assert_eq!(Pattern::parse("E* .").unwrap().min_keys(), 1);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Pattern::parse("E (R").is_err());
+        assert!(Pattern::parse("E <150").is_err());
+        assert!(Pattern::parse("E Unknown").is_err());
+        assert!(Pattern::parse("").is_err());
+        assert_eq!(parse_key("space"), Err("未知按键: space".to_string()));
+        assert_eq!(parse_key("Space"), Ok(Key::Space));
+    }
+
+    #[test]
+    fn test_key_name_roundtrip() {
+        for key in [Key::KeyE, Key::Num1, Key::Space, Key::ShiftLeft, Key::F1] {
+            assert_eq!(parse_key(&key_name(key)), Ok(key));
+        }
+        assert_eq!(key_name(Key::KeyE), "E");
+        assert_eq!(key_name(Key::Num1), "1");
+        assert_eq!(key_name(Key::F1), "F1");
+    }
+}
@@ -0,0 +1,79 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// 保存运行状态的目录：XDG 状态目录（$XDG_STATE_HOME/hero-rs），
+// 没有状态目录的平台使用本地数据目录
+pub fn state_dir() -> Option<PathBuf> {
+    Some(
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)?
+            .join("hero-rs"),
+    )
+}
+
+// 记录上次使用的英雄，下次启动时恢复
+#[derive(Debug, Clone)]
+pub struct ProfileStore {
+    path: PathBuf,
+}
+
+impl ProfileStore {
+    // 默认位置：状态目录下的 last_profile
+    pub fn new() -> Option<Self> {
+        Some(Self::at(state_dir()?.join("last_profile")))
+    }
+
+    pub fn at(path: PathBuf) -> Self {
+        ProfileStore { path }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    // 上次使用的英雄，没有记录时返回 None
+    pub fn load(&self) -> Option<String> {
+        let text = fs::read_to_string(&self.path).ok()?;
+        let name = text.trim();
+        (!name.is_empty()).then(|| name.to_string())
+    }
+
+    // 保存当前英雄，None 表示使用全局配置，删除记录
+    pub fn save(&self, profile: Option<&str>) -> io::Result<()> {
+        match profile {
+            Some(name) => {
+                if let Some(dir) = self.path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(&self.path, format!("{}\n", name))
+            }
+            None => match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = env::temp_dir().join(format!("hero-rs-profile-{}", process::id()));
+        let store = ProfileStore::at(dir.join("state").join("last_profile"));
+        assert_eq!(store.load(), None);
+
+        store.save(Some("Yasuo")).unwrap();
+        assert_eq!(store.load().as_deref(), Some("Yasuo"));
+        store.save(None).unwrap();
+        assert_eq!(store.load(), None);
+        store.save(None).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
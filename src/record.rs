@@ -0,0 +1,103 @@
+use rdev::{Event, EventType};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+// 录制的一个输入事件，offset_ms 是相对第一个事件的时间
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub offset_ms: u64,
+    pub event: EventType,
+}
+
+// 把输入事件按 JSON Lines 格式写入录制文件
+pub struct Recorder<W: Write> {
+    writer: W,
+    start: Option<SystemTime>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Self {
+        Recorder {
+            writer,
+            start: None,
+        }
+    }
+
+    // 记录一个事件并立即写入，鼠标移动不记录
+    pub fn record(&mut self, event: &Event) -> io::Result<()> {
+        if let EventType::MouseMove { .. } = event.event_type {
+            return Ok(());
+        }
+        let start = *self.start.get_or_insert(event.time);
+        let offset = event.time.duration_since(start).unwrap_or(Duration::ZERO);
+        let recorded = RecordedEvent {
+            offset_ms: offset.as_millis() as u64,
+            event: event.event_type,
+        };
+        serde_json::to_writer(&mut self.writer, &recorded)?;
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+}
+
+// 读取录制文件
+pub fn load(path: &Path) -> Result<Vec<RecordedEvent>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("无法读取录制文件 {}: {}", path.display(), e))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("录制文件 {} 第 {} 行格式错误: {}", path.display(), i + 1, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdev::Key;
+
+    #[test]
+    fn test_record_roundtrip() {
+        let start = SystemTime::now();
+        let event = |ms, event_type| Event {
+            time: start + Duration::from_millis(ms),
+            name: None,
+            event_type,
+        };
+        let mut recorder = Recorder::new(Vec::new());
+        recorder
+            .record(&event(0, EventType::KeyPress(Key::KeyE)))
+            .unwrap();
+        recorder
+            .record(&event(40, EventType::MouseMove { x: 1.0, y: 2.0 }))
+            .unwrap();
+        recorder
+            .record(&event(120, EventType::KeyPress(Key::KeyR)))
+            .unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("hero-rs-record-{}.jsonl", std::process::id()));
+        fs::write(&path, &recorder.writer).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            loaded,
+            [
+                RecordedEvent {
+                    offset_ms: 0,
+                    event: EventType::KeyPress(Key::KeyE)
+                },
+                RecordedEvent {
+                    offset_ms: 120,
+                    event: EventType::KeyPress(Key::KeyR)
+                },
+            ]
+        );
+    }
+}